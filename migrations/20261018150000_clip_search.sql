@@ -0,0 +1,25 @@
+-- Add migration script here
+CREATE VIRTUAL TABLE IF NOT EXISTS clips_fts USING fts5
+(
+    shortcode UNINDEXED,
+    title,
+    content
+);
+
+CREATE TRIGGER IF NOT EXISTS clips_fts_insert AFTER INSERT ON clips
+BEGIN
+    INSERT INTO clips_fts(shortcode, title, content) VALUES (new.shortcode, new.title, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS clips_fts_update AFTER UPDATE OF shortcode, title, content ON clips
+BEGIN
+    DELETE FROM clips_fts WHERE shortcode = old.shortcode;
+    INSERT INTO clips_fts(shortcode, title, content) VALUES (new.shortcode, new.title, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS clips_fts_delete AFTER DELETE ON clips
+BEGIN
+    DELETE FROM clips_fts WHERE shortcode = old.shortcode;
+END;
+
+INSERT INTO clips_fts(shortcode, title, content) SELECT shortcode, title, content FROM clips;
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS clip_revisions
+(
+    shortcode TEXT NOT NULL,
+    revision  BIGINT NOT NULL,
+    content   TEXT NOT NULL,
+    title     TEXT,
+    created   DATETIME NOT NULL,
+    PRIMARY KEY (shortcode, revision)
+);
+
+CREATE TRIGGER IF NOT EXISTS clip_revisions_delete AFTER DELETE ON clips
+BEGIN
+    DELETE FROM clip_revisions WHERE shortcode = old.shortcode;
+END;
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE api_keys ADD COLUMN label TEXT NOT NULL DEFAULT '';
+ALTER TABLE api_keys ADD COLUMN created DATETIME NOT NULL DEFAULT 0;
+ALTER TABLE api_keys ADD COLUMN expires DATETIME;
+ALTER TABLE api_keys ADD COLUMN last_used_at DATETIME;
+ALTER TABLE api_keys ADD COLUMN scopes TEXT NOT NULL DEFAULT 'read,write';
+
+UPDATE api_keys SET created = strftime('%s', 'now');
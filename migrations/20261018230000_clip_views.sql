@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS clip_views
+(
+    shortcode     TEXT NOT NULL,
+    bucket        DATETIME NOT NULL,
+    referrer_host TEXT NOT NULL,
+    agent         TEXT NOT NULL,
+    views         BIGINT NOT NULL,
+    PRIMARY KEY (shortcode, bucket, referrer_host, agent)
+);
+
+CREATE TRIGGER IF NOT EXISTS clip_views_delete AFTER DELETE ON clips
+BEGIN
+    DELETE FROM clip_views WHERE shortcode = old.shortcode;
+END;
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS clip_attachments
+(
+    shortcode TEXT PRIMARY KEY NOT NULL,
+    name      TEXT NOT NULL,
+    mime      TEXT NOT NULL,
+    size      BIGINT NOT NULL,
+    data      BLOB NOT NULL,
+    created   DATETIME NOT NULL
+);
+
+CREATE TRIGGER IF NOT EXISTS clip_attachments_delete AFTER DELETE ON clips
+BEGIN
+    DELETE FROM clip_attachments WHERE shortcode = old.shortcode;
+END;
@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE clips ADD COLUMN content_key TEXT;
+ALTER TABLE clips ADD COLUMN content_size BIGINT;
+ALTER TABLE clip_revisions ADD COLUMN content_key TEXT;
+ALTER TABLE clip_attachments ADD COLUMN data_key TEXT;
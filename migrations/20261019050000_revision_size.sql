@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE clip_revisions ADD COLUMN content_size BIGINT;
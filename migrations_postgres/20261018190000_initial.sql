@@ -0,0 +1,34 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS clips
+(
+    clip_id    TEXT PRIMARY KEY NOT NULL,
+    shortcode  TEXT UNIQUE NOT NULL,
+    content    TEXT NOT NULL,
+    title      TEXT,
+    posted     TIMESTAMP NOT NULL,
+    expires    TIMESTAMP,
+    password   TEXT,
+    hits       BIGINT NOT NULL,
+    edit_token TEXT,
+    max_views  BIGINT,
+    search     TSVECTOR GENERATED ALWAYS AS (
+        to_tsvector('simple', coalesce(title, '') || ' ' || content)
+    ) STORED
+);
+
+CREATE INDEX IF NOT EXISTS clips_search_idx ON clips USING GIN (search);
+
+CREATE TABLE IF NOT EXISTS clip_revisions
+(
+    shortcode TEXT NOT NULL REFERENCES clips (shortcode) ON DELETE CASCADE ON UPDATE CASCADE,
+    revision  BIGINT NOT NULL,
+    content   TEXT NOT NULL,
+    title     TEXT,
+    created   TIMESTAMP NOT NULL,
+    PRIMARY KEY (shortcode, revision)
+);
+
+CREATE TABLE IF NOT EXISTS api_keys
+(
+    api_key BYTEA PRIMARY KEY
+);
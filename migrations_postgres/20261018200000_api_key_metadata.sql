@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE api_keys ADD COLUMN label TEXT NOT NULL DEFAULT '';
+ALTER TABLE api_keys ADD COLUMN created TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC');
+ALTER TABLE api_keys ADD COLUMN expires TIMESTAMP;
+ALTER TABLE api_keys ADD COLUMN last_used_at TIMESTAMP;
+ALTER TABLE api_keys ADD COLUMN scopes TEXT NOT NULL DEFAULT 'read,write';
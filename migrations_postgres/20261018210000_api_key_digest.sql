@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Raw keys are moved aside and hashed into the new table by `Database::migrate`.
+ALTER TABLE api_keys RENAME TO legacy_api_keys;
+
+CREATE TABLE IF NOT EXISTS api_keys
+(
+    prefix       TEXT PRIMARY KEY NOT NULL,
+    digest       BYTEA NOT NULL,
+    label        TEXT NOT NULL DEFAULT '',
+    created      TIMESTAMP NOT NULL,
+    expires      TIMESTAMP,
+    last_used_at TIMESTAMP,
+    scopes       TEXT NOT NULL DEFAULT 'read,write'
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE clips ADD COLUMN api_key_prefix TEXT;
+CREATE INDEX IF NOT EXISTS clips_api_key_prefix_idx ON clips (api_key_prefix);
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS clip_views
+(
+    shortcode     TEXT NOT NULL REFERENCES clips (shortcode) ON DELETE CASCADE ON UPDATE CASCADE,
+    bucket        TIMESTAMP NOT NULL,
+    referrer_host TEXT NOT NULL,
+    agent         TEXT NOT NULL,
+    views         BIGINT NOT NULL,
+    PRIMARY KEY (shortcode, bucket, referrer_host, agent)
+);
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS clip_attachments
+(
+    shortcode TEXT PRIMARY KEY NOT NULL REFERENCES clips (shortcode) ON DELETE CASCADE ON UPDATE CASCADE,
+    name      TEXT NOT NULL,
+    mime      TEXT NOT NULL,
+    size      BIGINT NOT NULL,
+    data      BYTEA NOT NULL,
+    created   TIMESTAMP NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE clips ADD COLUMN content_zstd BYTEA;
+ALTER TABLE clip_revisions ADD COLUMN content_zstd BYTEA;
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- Plain text of content stored compressed or in the blob store, which leaves `content` empty.
+ALTER TABLE clips ADD COLUMN search_text TEXT;
+
+DROP INDEX IF EXISTS clips_search_idx;
+ALTER TABLE clips DROP COLUMN search;
+ALTER TABLE clips ADD COLUMN search TSVECTOR GENERATED ALWAYS AS (
+    to_tsvector('simple', coalesce(title, '') || ' ' || coalesce(search_text, content))
+) STORED;
+
+CREATE INDEX IF NOT EXISTS clips_search_idx ON clips USING GIN (search);
@@ -1,7 +1,10 @@
-use clip_ctash::domain::clip::field::{Content, Expires, Password, Shortcode, Title};
-use clip_ctash::service::ask::{GetClip, NewClip, UpdateClip};
-use clip_ctash::web::api::{ApiKey, API_KEY_HEADER};
+use clip_ctash::domain::clip::field::{
+    Content, EditToken, Expires, Format, Language, MaxViews, Password, Shortcode, Title,
+};
+use clip_ctash::service::ask::{DeleteClip, GetClip, NewClip, UpdateClip};
+use clip_ctash::web::api::{ApiKey, ErrorBody, API_KEY_HEADER, EDIT_TOKEN_HEADER, PASSWORD_HEADER};
 use clip_ctash::Clip;
+use reqwest::blocking::Response;
 use std::error::Error;
 use structopt::StructOpt;
 
@@ -22,17 +25,38 @@ enum Command {
         expires: Option<Expires>,
         #[structopt(short, long, help = "title")]
         title: Option<Title>,
+        #[structopt(short, long, help = "delete the clip after this many views")]
+        max_views: Option<MaxViews>,
+        #[structopt(short, long, help = "custom shortcode, e.g. oncall-runbook")]
+        shortcode: Option<Shortcode>,
+        #[structopt(short, long, help = "language to highlight, detected when omitted")]
+        language: Option<Language>,
+        #[structopt(short, long, help = "plain, markdown or code")]
+        format: Option<Format>,
     },
     Update {
         shortcode: Shortcode,
-        #[structopt(help = "content")]
-        clip: String,
+        #[structopt(help = "content, keeps the current content when omitted")]
+        clip: Option<String>,
+        #[structopt(long, help = "edit token returned when the clip was created")]
+        edit_token: EditToken,
         #[structopt(short, long, help = "password")]
         password: Option<Password>,
         #[structopt(short, long, help = "expiration data")]
         expires: Option<Expires>,
         #[structopt(short, long, help = "title")]
         title: Option<Title>,
+        #[structopt(long, help = "restore the content and title of this revision")]
+        restore: Option<u32>,
+        #[structopt(short, long, help = "language to highlight, detected when omitted")]
+        language: Option<Language>,
+    },
+    Delete {
+        shortcode: Shortcode,
+        #[structopt(long, help = "edit token returned when the clip was created")]
+        edit_token: EditToken,
+        #[structopt(short, long, help = "password")]
+        password: Option<String>,
     },
 }
 
@@ -49,16 +73,25 @@ struct Opt {
     api_key: ApiKey,
 }
 
+/// Passes successful responses through, and turns the error body of the others into an error.
+fn check(response: Response) -> Result<Response, Box<dyn Error>> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let error: ErrorBody = response.json()?;
+    Err(format!("{} ({})", error.message, error.code).into())
+}
+
 fn get_clip(addr: &str, ask: GetClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
     let addr = format!("{}/api/clip/{}", addr, ask.shortcode.into_inner());
     let mut request = client.get(addr);
     request = match ask.password.into_inner() {
-        Some(pwsd) => request.header(reqwest::header::COOKIE, format!("password={}", pwsd)),
+        Some(pwsd) => request.header(PASSWORD_HEADER, pwsd),
         None => request,
     };
     request = request.header(API_KEY_HEADER, api_key.to_base64());
-    Ok(request.send()?.json()?)
+    Ok(check(request.send()?)?.json()?)
 }
 
 fn new_clip(addr: &str, ask: NewClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
@@ -67,7 +100,7 @@ fn new_clip(addr: &str, ask: NewClip, api_key: ApiKey) -> Result<Clip, Box<dyn E
     let mut request = client.post(addr);
     request = request.header(API_KEY_HEADER, api_key.to_base64());
     //TODO need to dig
-    Ok(request.json(&ask).send()?.json()?)
+    Ok(check(request.json(&ask).send()?)?.json()?)
 }
 
 fn update_clip(addr: &str, ask: UpdateClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
@@ -76,7 +109,22 @@ fn update_clip(addr: &str, ask: UpdateClip, api_key: ApiKey) -> Result<Clip, Box
     let mut request = client.put(addr);
     request = request.header(API_KEY_HEADER, api_key.to_base64());
     //TODO need to dig
-    Ok(request.json(&ask).send()?.json()?)
+    Ok(check(request.json(&ask).send()?)?.json()?)
+}
+
+fn delete_clip(addr: &str, ask: DeleteClip, api_key: ApiKey) -> Result<(), Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip/{}", addr, ask.shortcode.into_inner());
+    let mut request = client.delete(addr);
+    request = match ask.password.into_inner() {
+        Some(pwsd) => request.header(PASSWORD_HEADER, pwsd),
+        None => request,
+    };
+    request = request
+        .header(EDIT_TOKEN_HEADER, ask.edit_token.into_inner())
+        .header(API_KEY_HEADER, api_key.to_base64());
+    check(request.send()?)?;
+    Ok(())
 }
 
 fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
@@ -98,22 +146,35 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
             password,
             expires,
             title,
+            max_views,
+            shortcode,
+            language,
+            format,
         } => {
             let req = NewClip {
                 content: Content::new(clip.as_str())?,
                 title: title.unwrap_or_default(),
                 expires: expires.unwrap_or_default(),
                 password: password.unwrap_or_default(),
+                max_views: max_views.unwrap_or_default(),
+                shortcode,
+                language: language.unwrap_or_default(),
+                format: format.unwrap_or_default(),
+                attachment: None,
             };
-            let _clip = new_clip(opt.addr.as_str(), req, opt.api_key)?;
+            let clip = new_clip(opt.addr.as_str(), req, opt.api_key)?;
+            println!("{:#?}", clip);
             Ok(())
         }
         Command::Update {
             shortcode,
             clip,
+            edit_token,
             password,
             expires,
             title,
+            restore,
+            language,
         } => {
             let password = password.unwrap_or_default();
             let req = GetClip {
@@ -121,17 +182,37 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
                 shortcode: shortcode.clone(),
             };
             let original_clip = get_clip(opt.addr.as_str(), req, opt.api_key.clone())?;
+            let content = match clip {
+                Some(clip) => Content::new(clip.as_str())?,
+                None => original_clip.content,
+            };
             let upd_req = UpdateClip {
-                content: Content::new(clip.as_str())?,
+                content,
                 expires: expires.unwrap_or(original_clip.expires),
                 title: title.unwrap_or(original_clip.title),
                 password,
                 shortcode,
+                edit_token,
+                restore,
+                language: language.unwrap_or(original_clip.language),
             };
             let clip = update_clip(opt.addr.as_str(), upd_req, opt.api_key)?;
             println!("{:#?}", clip);
             Ok(())
         }
+        Command::Delete {
+            shortcode,
+            edit_token,
+            password,
+        } => {
+            let req = DeleteClip {
+                shortcode,
+                password: Password::new(password.unwrap_or_default())?,
+                edit_token,
+            };
+            delete_clip(opt.addr.as_str(), req, opt.api_key)?;
+            Ok(())
+        }
     }
 }
 
@@ -1,40 +1,337 @@
+use clip_ctash::data::compression;
+use clip_ctash::data::query::RevocationStatus;
+use clip_ctash::data::storage::{self, Storage};
 use clip_ctash::data::AppDatabase;
+use clip_ctash::domain::api_key::Scopes;
+use clip_ctash::domain::clip::field::ShortcodeStyle;
+use clip_ctash::domain::limits::{self, Limits};
 use clip_ctash::domain::maintenance::Maintenance;
+use clip_ctash::service::action;
+use clip_ctash::service::ask::NewApiKey;
+use clip_ctash::web::api::ApiKey;
 use clip_ctash::web::counter::HitCounter;
+use clip_ctash::web::rate_limit::{Rate, RateLimiter};
 use clip_ctash::web::render::Renderer;
+use clip_ctash::Time;
 use dotenv::dotenv;
 use rocket::tokio;
 use std::path::PathBuf;
+use std::str::FromStr;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "httpd")]
 struct Opt {
-    #[structopt(default_value = "sqlite:data.db")]
+    #[structopt(
+        default_value = "sqlite:data.db",
+        help = "database url, postgres://... when built with the postgres feature"
+    )]
     connection_string: String,
     #[structopt(short, long, parse(from_os_str), default_value = "templates/")]
     template_directory: PathBuf,
+    #[structopt(
+        long,
+        parse(try_from_str = parse_lifetime),
+        help = "maximum clip lifetime, e.g. 12h or 30d"
+    )]
+    max_lifetime: Option<chrono::Duration>,
+    #[structopt(
+        long,
+        help = "how new shortcodes look: base62[:length], words[:count] or chars:<alphabet>:<length>"
+    )]
+    shortcode_style: Option<ShortcodeStyle>,
+    #[structopt(
+        long,
+        default_value = "5s",
+        parse(try_from_str = parse_interval),
+        help = "how often collected clip hits are written, e.g. 5s or 1m"
+    )]
+    hit_flush_interval: std::time::Duration,
+    #[structopt(
+        long,
+        default_value = "120",
+        help = "API requests per minute per key, 0 to disable"
+    )]
+    api_rate: u32,
+    #[structopt(
+        long,
+        default_value = "60",
+        help = "API requests a key may burst at once"
+    )]
+    api_burst: u32,
+    #[structopt(
+        long,
+        default_value = "60",
+        help = "page requests per minute per client IP, 0 to disable"
+    )]
+    html_rate: u32,
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "page requests a client may burst at once"
+    )]
+    html_burst: u32,
+    #[structopt(long, help = "most clips a single API key may store")]
+    max_clips_per_key: Option<u64>,
+    #[structopt(long, help = "most bytes of content a single API key may store")]
+    max_bytes_per_key: Option<u64>,
+    #[structopt(long, help = "largest file that may be attached to a clip, in bytes")]
+    max_attachment_bytes: Option<u64>,
+    #[structopt(long, help = "longest clip content in bytes, 1 MiB by default")]
+    max_content_bytes: Option<u64>,
+    #[structopt(long, help = "longest clip title in characters, 200 by default")]
+    max_title_chars: Option<usize>,
+    #[structopt(
+        long,
+        help = "keep large content outside the database: file:<directory> or s3://<bucket>[/<prefix>]"
+    )]
+    blob_store: Option<String>,
+    #[structopt(
+        long,
+        help = "content and files larger than this many bytes go to the blob store, 64 KiB by default"
+    )]
+    blob_threshold: Option<usize>,
+    #[structopt(
+        long,
+        help = "compress content larger than this many bytes, 8 KiB by default, 0 to disable"
+    )]
+    compress_above: Option<usize>,
+    #[structopt(long, help = "do not apply pending database migrations on startup")]
+    no_migrate: bool,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    #[structopt(about = "inspect or apply the embedded database migrations")]
+    Migrate(MigrateCommand),
+    #[structopt(about = "manage API keys")]
+    Keys(KeysCommand),
+}
+
+#[derive(StructOpt, Debug)]
+enum MigrateCommand {
+    #[structopt(about = "list the embedded migrations and whether they have been applied")]
+    Status,
+    #[structopt(about = "apply pending migrations and exit")]
+    Run,
+}
+
+#[derive(StructOpt, Debug)]
+enum KeysCommand {
+    #[structopt(about = "issue a new key, printed only once")]
+    Create {
+        #[structopt(long, default_value = "", help = "name to recognise the key by")]
+        label: String,
+        #[structopt(long, default_value = "read,write", help = "read, write and/or admin")]
+        scopes: Scopes,
+        #[structopt(
+            long,
+            parse(try_from_str = parse_expiry),
+            help = "expiration, e.g. 90d or 2027-01-01"
+        )]
+        expires: Option<Time>,
+    },
+    #[structopt(about = "list every key without revealing it")]
+    List,
+    #[structopt(about = "revoke a key")]
+    Revoke {
+        #[structopt(required_unless = "prefix", help = "the full key")]
+        key: Option<ApiKey>,
+        #[structopt(long, conflicts_with = "key", help = "prefix shown by `keys list`")]
+        prefix: Option<String>,
+    },
+}
+
+fn parse_lifetime(raw: &str) -> Result<chrono::Duration, String> {
+    clip_ctash::domain::time::parse_duration(raw)
+        .ok_or_else(|| format!("invalid lifetime '{}', expected e.g. 10m, 1h or 7d", raw))
+}
+
+async fn migrate(command: MigrateCommand, database: &AppDatabase) {
+    match command {
+        MigrateCommand::Status => {
+            let migrations = database
+                .migration_status()
+                .await
+                .expect("failed to read migration status");
+            for migration in migrations {
+                let state = if migration.applied {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                println!(
+                    "{}\t{}\t{}",
+                    migration.version, state, migration.description
+                );
+            }
+        }
+        MigrateCommand::Run => {
+            database
+                .migrate()
+                .await
+                .expect("failed to apply database migrations");
+            println!("database is up to date");
+        }
+    }
+}
+
+fn parse_interval(raw: &str) -> Result<std::time::Duration, String> {
+    clip_ctash::domain::time::parse_duration(raw)
+        .and_then(|duration| duration.to_std().ok())
+        .ok_or_else(|| format!("invalid interval '{}', expected e.g. 5s or 1m", raw))
+}
+
+fn parse_expiry(raw: &str) -> Result<Time, String> {
+    match clip_ctash::domain::time::parse_duration(raw) {
+        Some(duration) => Time::from_now(duration).ok_or_else(|| "expiration too far".to_owned()),
+        None => Time::from_str(raw).map_err(|e| format!("invalid expiration '{}': {}", raw, e)),
+    }
+}
+
+fn rate(per_minute: u32, burst: u32) -> Option<Rate> {
+    (per_minute > 0).then_some(Rate { per_minute, burst })
+}
+
+fn describe(time: Option<Time>) -> String {
+    time.map(|time| time.into_inner().to_rfc3339())
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+async fn keys(command: KeysCommand, database: &AppDatabase) {
+    let pool = database.get_pool();
+    match command {
+        KeysCommand::Create {
+            label,
+            scopes,
+            expires,
+        } => {
+            let req = NewApiKey {
+                label,
+                expires,
+                scopes,
+            };
+            let (key, info) = action::generate_api_key(req, pool)
+                .await
+                .expect("failed to create API key");
+            println!("{}", key.to_base64());
+            eprintln!(
+                "created key {} with scopes {}, it will not be shown again",
+                info.prefix, info.scopes
+            );
+        }
+        KeysCommand::List => {
+            let keys = action::list_api_keys(pool)
+                .await
+                .expect("failed to list API keys");
+            for key in keys {
+                println!(
+                    "{}\t{}\t{}\tcreated {}\texpires {}\tlast used {}",
+                    key.prefix,
+                    key.scopes,
+                    key.label,
+                    describe(Some(key.created)),
+                    describe(key.expires),
+                    describe(key.last_used_at)
+                );
+            }
+        }
+        KeysCommand::Revoke { key, prefix } => {
+            let status = match (key, prefix) {
+                (Some(key), _) => action::revoke_api_key(key, pool).await,
+                (None, Some(prefix)) => action::revoke_api_key_by_prefix(&prefix, pool).await,
+                (None, None) => unreachable!("structopt requires a key or a prefix"),
+            };
+            match status.expect("failed to revoke API key") {
+                RevocationStatus::Revoked => println!("key revoked"),
+                RevocationStatus::NotFound => {
+                    eprintln!("no such key");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
 }
 
 fn main() {
     dotenv().ok();
     let opt = Opt::from_args();
+    limits::configure(Limits {
+        max_lifetime: opt.max_lifetime,
+        max_clips_per_key: opt.max_clips_per_key,
+        max_bytes_per_key: opt.max_bytes_per_key,
+        max_attachment_bytes: opt.max_attachment_bytes,
+        max_content_bytes: opt.max_content_bytes,
+        max_title_chars: opt.max_title_chars,
+    });
+    if let Some(style) = opt.shortcode_style.clone() {
+        ShortcodeStyle::configure(style);
+    }
+    if let Some(threshold) = opt.compress_above {
+        compression::configure(Some(threshold).filter(|threshold| *threshold > 0));
+    }
+    if let Some(url) = &opt.blob_store {
+        let storage = Storage::open(
+            url,
+            opt.blob_threshold.unwrap_or(storage::DEFAULT_THRESHOLD),
+        )
+        .expect("failed to open blob store");
+        Storage::configure(storage);
+    }
 
     let rt = tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime");
 
     let handle = rt.handle().clone();
     let renderer = Renderer::new(opt.template_directory.clone());
 
-    let database = rt.block_on(async move { AppDatabase::new(&opt.connection_string).await });
+    let create = match opt.command {
+        Some(Command::Migrate(MigrateCommand::Status)) => false,
+        Some(Command::Migrate(MigrateCommand::Run)) => true,
+        Some(Command::Keys(_)) | None => !opt.no_migrate,
+    };
+    let connection_string = opt.connection_string.clone();
+    let database = rt.block_on(async move {
+        if create {
+            AppDatabase::create_if_missing(&connection_string)
+                .await
+                .expect("failed to create database");
+        }
+        AppDatabase::new(&connection_string).await
+    });
+
+    if let Some(Command::Migrate(command)) = opt.command {
+        rt.block_on(migrate(command, &database));
+        return;
+    }
+    if !opt.no_migrate {
+        rt.block_on(database.migrate())
+            .expect("failed to apply database migrations");
+    }
+    if let Some(Command::Keys(command)) = opt.command {
+        rt.block_on(keys(command, &database));
+        return;
+    }
 
-    let hit_counter = HitCounter::new(database.get_pool().clone(), handle.clone());
+    let hit_counter = HitCounter::new(
+        database.get_pool().clone(),
+        handle.clone(),
+        opt.hit_flush_interval,
+    );
     let maintenance = Maintenance::spawn(database.get_pool().clone(), handle.clone());
 
+    let rate_limiter = RateLimiter::new(
+        rate(opt.api_rate, opt.api_burst),
+        rate(opt.html_rate, opt.html_burst),
+    );
+
     let config = clip_ctash::RocketConfig {
         renderer,
         database,
         hit_counter,
         maintenance,
+        rate_limiter,
     };
 
     rt.block_on(async move {
@@ -0,0 +1,64 @@
+//! zstd compression of clip content.
+//!
+//! Content above the threshold is stored compressed when that makes it smaller. Its plain
+//! text is still indexed for search, see `query::store_content`.
+
+use parking_lot::RwLock;
+
+/// Content up to this many bytes is stored as is when no threshold is configured.
+pub const DEFAULT_THRESHOLD: usize = 8 * 1024;
+
+/// Favours speed, logs and JSON already shrink well at low levels.
+const LEVEL: i32 = 3;
+
+/// Every zstd frame starts with these bytes. They are not valid UTF-8, so compressed
+/// content can always be told apart from text.
+const MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+static THRESHOLD: RwLock<Option<Option<usize>>> = RwLock::new(None);
+
+/// Compresses content larger than `threshold` bytes, `None` turns compression off.
+pub fn configure(threshold: Option<usize>) {
+    *THRESHOLD.write() = Some(threshold);
+}
+
+pub fn threshold() -> Option<usize> {
+    THRESHOLD.read().unwrap_or(Some(DEFAULT_THRESHOLD))
+}
+
+/// The compressed `content`, or `None` when it is small or does not compress.
+pub fn compress(content: &str) -> Option<Vec<u8>> {
+    let threshold = threshold()?;
+    if content.len() <= threshold {
+        return None;
+    }
+    zstd::encode_all(content.as_bytes(), LEVEL)
+        .ok()
+        .filter(|compressed| compressed.len() < content.len())
+}
+
+pub fn decompress(data: &[u8]) -> Result<String, String> {
+    let data = zstd::decode_all(data).map_err(|e| e.to_string())?;
+    String::from_utf8(data).map_err(|e| e.to_string())
+}
+
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_large_content_is_compressed() {
+        let log = "GET /clip/abc 200 OK\n".repeat(1000);
+        let compressed = compress(&log).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < log.len() / 10);
+        assert_eq!(decompress(&compressed).unwrap(), log);
+
+        assert!(compress("short").is_none());
+        assert!(!is_compressed(log.as_bytes()));
+    }
+}
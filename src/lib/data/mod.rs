@@ -1,47 +1,135 @@
+pub mod compression;
 pub mod model;
 pub mod query;
+pub mod storage;
 
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
-use sqlx::Sqlite;
+use sqlx::migrate::{Migrate, MigrateDatabase};
 use std::fmt::Debug;
 use std::str::FromStr;
 use uuid::Uuid;
 
+#[cfg(all(feature = "sqlite", feature = "postgres"))]
+compile_error!("the `sqlite` and `postgres` features are mutually exclusive");
+
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("enable a database backend with either the `sqlite` or `postgres` feature");
+
+#[cfg(feature = "sqlite")]
+mod backend {
+    pub type Backend = sqlx::Sqlite;
+    pub type DatabasePool = sqlx::sqlite::SqlitePool;
+    pub type AppDatabaseRow = sqlx::sqlite::SqliteRow;
+    pub type AppQueryResult = sqlx::sqlite::SqliteQueryResult;
+    pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations");
+    /// Extended result code of a UNIQUE constraint failure.
+    pub const UNIQUE_VIOLATION: &str = "2067";
+}
+
+#[cfg(feature = "postgres")]
+mod backend {
+    pub type Backend = sqlx::Postgres;
+    pub type DatabasePool = sqlx::postgres::PgPool;
+    pub type AppDatabaseRow = sqlx::postgres::PgRow;
+    pub type AppQueryResult = sqlx::postgres::PgQueryResult;
+    pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations_postgres");
+    /// SQLSTATE of a unique_violation.
+    pub const UNIQUE_VIOLATION: &str = "23505";
+}
+
+pub use backend::{
+    AppDatabaseRow, AppQueryResult, Backend, DatabasePool, MIGRATOR, UNIQUE_VIOLATION,
+};
+
 #[derive(Debug, thiserror::Error)]
 pub enum DataError {
     #[error("database error: {0}")]
     Database(#[from] sqlx::Error),
+    #[error("migration error: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("shortcode already in use")]
+    ShortcodeTaken,
+    #[error("{0}")]
+    QuotaExceeded(String),
+    #[error("storage error: {0}")]
+    Storage(#[from] storage::StorageError),
+}
+
+/// A migration embedded in the binary and whether it has been applied.
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
 }
 
-pub type AppDatabase = Database<Sqlite>;
-pub type DatabasePool = sqlx::sqlite::SqlitePool;
-pub type Transaction<'t> = sqlx::Transaction<'t, Sqlite>;
-pub type AppDatabaseRow = sqlx::sqlite::SqliteRow;
-pub type AppQueryResult = sqlx::sqlite::SqliteQueryResult;
+pub type AppDatabase = Database<Backend>;
+pub type Transaction<'t> = sqlx::Transaction<'t, Backend>;
 
 pub struct Database<D: sqlx::Database>(sqlx::Pool<D>);
 
-impl Database<Sqlite> {
+impl<D: sqlx::Database> Database<D> {
     pub async fn new(connection_str: &str) -> Self {
-        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        let pool = sqlx::pool::PoolOptions::<D>::new()
             .connect(connection_str)
             .await;
         match pool {
             Ok(pool) => Self(pool),
             Err(e) => {
                 eprintln!("{}\n", e);
-                eprintln!("If database has not yet been created, run: \n $ sqlx database setup");
+                eprintln!("Check the connection string and that the database server is running");
                 panic!("database connection error!")
             }
         }
     }
 
-    pub fn get_pool(&self) -> &DatabasePool {
+    pub fn get_pool(&self) -> &sqlx::Pool<D> {
         &self.0
     }
 }
 
+impl<D: sqlx::Database + MigrateDatabase> Database<D> {
+    /// Creates the database when it does not exist yet, e.g. a fresh SQLite file.
+    pub async fn create_if_missing(connection_str: &str) -> Result<(), DataError> {
+        if !D::database_exists(connection_str).await? {
+            D::create_database(connection_str).await?;
+        }
+        Ok(())
+    }
+}
+
+impl AppDatabase {
+    /// Applies every embedded migration that has not been run yet, then hashes any
+    /// API keys still stored in plain text and indexes stored content missing from search.
+    pub async fn migrate(&self) -> Result<(), DataError> {
+        MIGRATOR.run(self.get_pool()).await?;
+        query::upgrade_legacy_api_keys(self.get_pool()).await?;
+        query::index_stored_content(self.get_pool()).await?;
+        Ok(())
+    }
+
+    /// Lists the embedded migrations alongside the ones recorded in the database.
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>, DataError> {
+        let mut conn = self.get_pool().acquire().await?;
+        conn.ensure_migrations_table().await?;
+        let applied = conn
+            .list_applied_migrations()
+            .await?
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect::<Vec<_>>();
+        Ok(MIGRATOR
+            .iter()
+            .map(|migration| MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                applied: applied.contains(&migration.version),
+            })
+            .collect())
+    }
+}
+
 #[derive(Clone, Debug, From, Display, Serialize, Deserialize)]
 pub struct Dbid(Uuid);
 
@@ -77,18 +165,94 @@ impl FromStr for Dbid {
 #[cfg(test)]
 pub mod test {
     use crate::data::*;
-    use rocket::http::uri::fmt::Kind::Path;
-    use sqlx::migrate::Migrator;
     use tokio::runtime::Handle;
 
+    #[cfg(feature = "sqlite")]
     pub fn new_db(handle: &Handle) -> AppDatabase {
-        use std::path::Path;
         handle.block_on(async move {
             let db = Database::new(":memory:").await;
-            let migrator = Migrator::new(Path::new("./migrations")).await.unwrap();
-            let pool = db.get_pool();
-            migrator.run(pool).await.unwrap();
+            db.migrate().await.unwrap();
+            db
+        })
+    }
+
+    /// Every test gets a fresh database on a shared server, either the one in
+    /// `TEST_POSTGRES_URL` or a throwaway cluster started on first use.
+    #[cfg(feature = "postgres")]
+    pub fn new_db(handle: &Handle) -> AppDatabase {
+        let server = postgres::server_url();
+        handle.block_on(async move {
+            let name = format!("clipstash_test_{}", Uuid::new_v4().to_simple());
+            let admin = AppDatabase::new(&format!("{}/postgres", server)).await;
+            sqlx::query(&format!("CREATE DATABASE {}", name))
+                .execute(admin.get_pool())
+                .await
+                .unwrap();
+            let db = Database::new(&format!("{}/{}", server, name)).await;
+            db.migrate().await.unwrap();
             db
         })
     }
+
+    #[cfg(feature = "postgres")]
+    mod postgres {
+        use std::net::TcpListener;
+        use std::process::{Command, Stdio};
+        use std::sync::OnceLock;
+        use std::time::Duration;
+
+        pub fn server_url() -> &'static str {
+            static URL: OnceLock<String> = OnceLock::new();
+            URL.get_or_init(|| std::env::var("TEST_POSTGRES_URL").unwrap_or_else(|_| start()))
+        }
+
+        /// Runs `initdb` and `postgres` from the PATH in a temporary directory. A small
+        /// shell watchdog stops the server and removes the cluster once the test
+        /// process has exited.
+        fn start() -> String {
+            let pid = std::process::id();
+            let dir = std::env::temp_dir().join(format!("clipstash-pg-{}", pid));
+            let port = TcpListener::bind("127.0.0.1:0")
+                .and_then(|listener| listener.local_addr())
+                .expect("failed to find a free port")
+                .port();
+            let initdb = Command::new("initdb")
+                .arg("-D")
+                .arg(&dir)
+                .args(["-U", "postgres", "--auth=trust"])
+                .stdout(Stdio::null())
+                .status()
+                .expect("initdb must be on the PATH, or set TEST_POSTGRES_URL");
+            assert!(initdb.success(), "initdb failed");
+
+            let script = format!(
+                "postgres -D {dir} -p {port} -k {dir} -c listen_addresses=127.0.0.1 & pg=$!; \
+                 while kill -0 {pid} 2>/dev/null; do sleep 1; done; \
+                 kill $pg; wait $pg; rm -rf {dir}",
+                dir = dir.display(),
+                port = port,
+                pid = pid,
+            );
+            Command::new("sh")
+                .args(["-c", &script])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("failed to start postgres");
+
+            let port = port.to_string();
+            for _ in 0..100 {
+                let ready = Command::new("pg_isready")
+                    .args(["-q", "-h", "127.0.0.1", "-p", &port])
+                    .status()
+                    .map(|status| status.success())
+                    .unwrap_or(false);
+                if ready {
+                    return format!("postgres://postgres@127.0.0.1:{}", port);
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            panic!("postgres did not start")
+        }
+    }
 }
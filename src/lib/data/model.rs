@@ -1,4 +1,4 @@
-use crate::data::Dbid;
+use crate::data::{compression, Dbid};
 use crate::{ClipError, Shortcode, Time};
 use chrono::{NaiveDateTime, Utc};
 use std::convert::TryFrom;
@@ -8,11 +8,22 @@ pub struct Clip {
     pub(in crate::data) clip_id: String,
     pub(in crate::data) shortcode: String,
     pub(in crate::data) content: String,
+    /// Set when the content is kept in the blob store instead of `content`.
+    pub(in crate::data) content_key: Option<String>,
+    /// Set when the content is stored compressed instead of `content`.
+    pub(in crate::data) content_zstd: Option<Vec<u8>>,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) posted: NaiveDateTime,
     pub(in crate::data) expires: Option<NaiveDateTime>,
     pub(in crate::data) password: Option<String>,
     pub(in crate::data) hits: i64,
+    pub(in crate::data) edit_token: Option<String>,
+    pub(in crate::data) max_views: Option<i64>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) format: String,
+    pub(in crate::data) attachment_name: Option<String>,
+    pub(in crate::data) attachment_mime: Option<String>,
+    pub(in crate::data) attachment_size: Option<i64>,
 }
 
 impl TryFrom<Clip> for crate::domain::Clip {
@@ -23,16 +34,233 @@ impl TryFrom<Clip> for crate::domain::Clip {
         Ok(Self {
             clip_id: field::ClipId::new(Dbid::from_str(clip.clip_id.as_str())?),
             shortcode: field::Shortcode::from(clip.shortcode),
-            content: field::Content::new(clip.content.as_str())?,
+            content: field::Content::new(&stored_content(clip.content, &clip.content_zstd)?)?,
             title: field::Title::new(clip.title),
             posted: field::Posted::new(Time::from_naive_utc(clip.posted)),
             expires: field::Expires::new(clip.expires.map(Time::from_naive_utc)),
             password: field::Password::new(clip.password.unwrap_or_default())?,
             hits: field::Hits::new(u64::try_from(clip.hits)?),
+            max_views: field::MaxViews::new(
+                clip.max_views
+                    .map(u32::try_from)
+                    .transpose()
+                    .map_err(|e| ClipError::InvalidMaxViews(e.to_string()))?,
+            ),
+            edit_token: None,
+            language: field::Language::new(clip.language)?,
+            format: field::Format::from_str(&clip.format)?,
+            attachment: match (
+                clip.attachment_name,
+                clip.attachment_mime,
+                clip.attachment_size,
+            ) {
+                (Some(name), Some(mime), Some(size)) => Some(crate::domain::clip::Attachment {
+                    name,
+                    mime,
+                    size: u64::try_from(size)?,
+                }),
+                _ => None,
+            },
+            content_zstd: clip.content_zstd,
         })
     }
 }
 
+/// Content of a row, decompressed when it was stored compressed.
+fn stored_content(content: String, zstd: &Option<Vec<u8>>) -> Result<String, ClipError> {
+    match zstd {
+        Some(data) => compression::decompress(data).map_err(ClipError::Decompress),
+        None => Ok(content),
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Attachment {
+    pub(in crate::data) name: String,
+    pub(in crate::data) mime: String,
+    pub(in crate::data) size: i64,
+    pub(in crate::data) data: Vec<u8>,
+    /// Set when the data is kept in the blob store instead of `data`.
+    pub(in crate::data) data_key: Option<String>,
+}
+
+impl From<crate::domain::clip::AttachmentData> for Attachment {
+    fn from(file: crate::domain::clip::AttachmentData) -> Self {
+        Self {
+            name: file.attachment.name,
+            mime: file.attachment.mime,
+            size: i64::try_from(file.attachment.size).unwrap_or(i64::MAX),
+            data: file.data,
+            data_key: None,
+        }
+    }
+}
+
+impl TryFrom<Attachment> for crate::domain::clip::AttachmentData {
+    type Error = ClipError;
+    fn try_from(attachment: Attachment) -> Result<Self, Self::Error> {
+        Ok(Self {
+            attachment: crate::domain::clip::Attachment {
+                name: attachment.name,
+                mime: attachment.mime,
+                size: u64::try_from(attachment.size)?,
+            },
+            data: attachment.data,
+        })
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Revision {
+    pub(in crate::data) shortcode: String,
+    pub(in crate::data) revision: i64,
+    pub(in crate::data) content: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) created: NaiveDateTime,
+    pub(in crate::data) content_key: Option<String>,
+    pub(in crate::data) content_zstd: Option<Vec<u8>>,
+}
+
+impl TryFrom<Revision> for crate::domain::clip::Revision {
+    type Error = ClipError;
+    fn try_from(revision: Revision) -> Result<Self, Self::Error> {
+        use crate::domain::clip::field;
+        Ok(Self {
+            shortcode: field::Shortcode::from(revision.shortcode),
+            revision: u32::try_from(revision.revision)?,
+            content: field::Content::new(&stored_content(
+                revision.content,
+                &revision.content_zstd,
+            )?)?,
+            title: field::Title::new(revision.title),
+            created: Time::from_naive_utc(revision.created),
+        })
+    }
+}
+
+/// Marks matched terms in `snippet()` output, chosen so they cannot clash with clip text.
+pub(in crate::data) const MATCH_START: &str = "\u{2}";
+pub(in crate::data) const MATCH_END: &str = "\u{3}";
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct SearchResult {
+    pub(in crate::data) shortcode: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) snippet: String,
+    pub(in crate::data) rank: f64,
+}
+
+impl From<SearchResult> for crate::domain::clip::SearchResult {
+    fn from(result: SearchResult) -> Self {
+        use crate::domain::clip::{field, SnippetMatch};
+        let mut snippet = String::with_capacity(result.snippet.len());
+        let mut matches = vec![];
+        let mut rest = result.snippet.as_str();
+        while let Some(start) = rest.find(MATCH_START) {
+            snippet.push_str(&rest[..start]);
+            rest = &rest[start + MATCH_START.len()..];
+            let end = rest.find(MATCH_END).unwrap_or(rest.len());
+            matches.push(SnippetMatch {
+                start: snippet.len(),
+                end: snippet.len() + end,
+            });
+            snippet.push_str(&rest[..end]);
+            rest = rest.get(end + MATCH_END.len()..).unwrap_or_default();
+        }
+        snippet.push_str(rest);
+        Self {
+            shortcode: field::Shortcode::from(result.shortcode),
+            title: field::Title::new(result.title),
+            snippet,
+            matches,
+            rank: result.rank,
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ApiKey {
+    pub(in crate::data) prefix: String,
+    pub(in crate::data) digest: Vec<u8>,
+    pub(in crate::data) label: String,
+    pub(in crate::data) created: NaiveDateTime,
+    pub(in crate::data) expires: Option<NaiveDateTime>,
+    pub(in crate::data) last_used_at: Option<NaiveDateTime>,
+    pub(in crate::data) scopes: String,
+}
+
+impl ApiKey {
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+}
+
+impl From<ApiKey> for crate::domain::api_key::ApiKeyInfo {
+    fn from(key: ApiKey) -> Self {
+        use crate::domain::api_key::Scopes;
+        Self {
+            prefix: key.prefix,
+            label: key.label,
+            created: Time::from_naive_utc(key.created),
+            expires: key.expires.map(Time::from_naive_utc),
+            last_used_at: key.last_used_at.map(Time::from_naive_utc),
+            scopes: Scopes::from_stored(&key.scopes),
+        }
+    }
+}
+
+pub struct NewApiKey {
+    pub(in crate::data) prefix: String,
+    pub(in crate::data) digest: Vec<u8>,
+    pub(in crate::data) label: String,
+    pub(in crate::data) created: i64,
+    pub(in crate::data) expires: Option<i64>,
+    pub(in crate::data) scopes: String,
+}
+
+impl NewApiKey {
+    pub fn new(api_key: &crate::web::api::ApiKey, req: crate::service::ask::NewApiKey) -> Self {
+        Self {
+            prefix: api_key.prefix(),
+            digest: api_key.digest(),
+            label: req.label,
+            created: Utc::now().timestamp(),
+            expires: req.expires.map(|time| time.timestamp()),
+            scopes: req.scopes.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ViewBucket {
+    pub(in crate::data) bucket: i64,
+    pub(in crate::data) views: i64,
+}
+
+impl From<ViewBucket> for crate::domain::views::ViewBucket {
+    fn from(bucket: ViewBucket) -> Self {
+        Self::new(
+            bucket.bucket,
+            u64::try_from(bucket.views).unwrap_or_default(),
+        )
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ViewCount {
+    pub(in crate::data) name: String,
+    pub(in crate::data) views: i64,
+}
+
+impl From<ViewCount> for crate::domain::views::ViewCount {
+    fn from(count: ViewCount) -> Self {
+        Self {
+            name: count.name,
+            views: u64::try_from(count.views).unwrap_or_default(),
+        }
+    }
+}
+
 impl From<crate::service::ask::GetClip> for GetClip {
     fn from(value: crate::service::ask::GetClip) -> Self {
         Self {
@@ -59,6 +287,7 @@ impl From<String> for GetClip {
     }
 }
 
+#[derive(Clone)]
 pub struct NewClip {
     pub(in crate::data) clip_id: String,
     pub(in crate::data) shortcode: String,
@@ -67,38 +296,121 @@ pub struct NewClip {
     pub(in crate::data) posted: i64,
     pub(in crate::data) expires: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) edit_token: Option<String>,
+    pub(in crate::data) max_views: Option<i64>,
+    pub(in crate::data) api_key_prefix: Option<String>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) format: String,
+    pub(in crate::data) attachment: Option<Attachment>,
+    pub(in crate::data) quota: Option<Quota>,
+}
+
+impl NewClip {
+    /// Records the hashed edit token of the clip creator.
+    pub fn with_edit_token(self, edit_token: String) -> Self {
+        Self {
+            edit_token: Some(edit_token),
+            ..self
+        }
+    }
+
+    /// Replaces the shortcode, used to retry after a collision.
+    pub fn with_shortcode(self, shortcode: Shortcode) -> Self {
+        Self {
+            shortcode: shortcode.into(),
+            ..self
+        }
+    }
+
+    /// Counts the clip against the quotas of the API key that created it.
+    pub fn with_api_key(self, api_key_prefix: Option<String>) -> Self {
+        Self {
+            api_key_prefix,
+            ..self
+        }
+    }
+
+    /// Rejects the clip when storing it would take its API key past `quota`.
+    pub fn with_quota(self, quota: Option<Quota>) -> Self {
+        Self { quota, ..self }
+    }
 }
 
 impl From<crate::service::ask::NewClip> for NewClip {
     fn from(req: crate::service::ask::NewClip) -> Self {
         Self {
             clip_id: Dbid::new().into(),
-            shortcode: Shortcode::default().into(),
+            shortcode: req.shortcode.unwrap_or_default().into(),
             content: req.content.into_inner(),
             title: req.title.into_inner(),
             expires: req.expires.into_inner().map(|time| time.timestamp()),
             password: req.password.into_inner(),
             posted: Utc::now().timestamp(),
+            edit_token: None,
+            max_views: req.max_views.into_inner().map(i64::from),
+            api_key_prefix: None,
+            language: req.language.into_inner(),
+            format: req.format.as_str().to_owned(),
+            attachment: req.attachment.map(Attachment::from),
+            quota: None,
         }
     }
 }
 
+/// Most clips and bytes an API key may store, checked in the transaction that writes a
+/// clip of the key so that concurrent requests cannot all slip past them.
+#[derive(Debug, Clone)]
+pub struct Quota {
+    pub(in crate::data) api_key_prefix: String,
+    pub(in crate::data) max_clips: Option<i64>,
+    pub(in crate::data) max_bytes: Option<i64>,
+}
+
+impl Quota {
+    pub fn new(api_key_prefix: String, max_clips: Option<u64>, max_bytes: Option<u64>) -> Self {
+        let limit = |max: u64| i64::try_from(max).unwrap_or(i64::MAX);
+        Self {
+            api_key_prefix,
+            max_clips: max_clips.map(limit),
+            max_bytes: max_bytes.map(limit),
+        }
+    }
+}
+
+/// Clips and bytes of content stored by one API key.
+#[derive(Debug)]
+pub struct KeyUsage {
+    pub clips: i64,
+    pub bytes: i64,
+}
+
 pub struct UpdateClip {
     pub(in crate::data) shortcode: String,
     pub(in crate::data) content: String,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) expires: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) quota: Option<Quota>,
+}
+
+impl UpdateClip {
+    /// Rejects the edit when it would take the API key of the clip past `quota`.
+    pub fn with_quota(self, quota: Option<Quota>) -> Self {
+        Self { quota, ..self }
+    }
 }
 
 impl From<crate::service::ask::UpdateClip> for UpdateClip {
     fn from(req: crate::service::ask::UpdateClip) -> Self {
         Self {
-            shortcode: Shortcode::default().into(),
+            shortcode: req.shortcode.into_inner(),
             content: req.content.into_inner(),
             title: req.title.into_inner(),
             expires: req.expires.into_inner().map(|time| time.timestamp()),
             password: req.password.into_inner(),
+            language: req.language.into_inner(),
+            quota: None,
         }
     }
 }
@@ -0,0 +1,718 @@
+use crate::data::compression;
+use crate::data::storage::{Storage, StorageError};
+use crate::data::{model, DataError, DatabasePool, Transaction, UNIQUE_VIOLATION};
+use crate::Shortcode;
+use std::collections::HashSet;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::*;
+
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "postgres")]
+pub use postgres::*;
+
+type Result<T> = std::result::Result<T, DataError>;
+
+pub enum RevocationStatus {
+    Revoked,
+    NotFound,
+}
+
+/// Reports an insert that clashed with an existing shortcode as `DataError::ShortcodeTaken`.
+fn shortcode_taken(err: sqlx::Error) -> DataError {
+    match &err {
+        sqlx::Error::Database(e) if e.code().as_deref() == Some(UNIQUE_VIOLATION) => {
+            DataError::ShortcodeTaken
+        }
+        _ => err.into(),
+    }
+}
+
+/// Fails when the clip written in `transaction` took the key of `quota` past its limits,
+/// rolling the write back with the transaction. The key was locked by [`lock_api_key`]
+/// before writing, so requests of one key are checked one after the other. Edits keep
+/// the number of clips and only have to stay within the bytes.
+async fn check_quota(
+    quota: &model::Quota,
+    new_clip: bool,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    let usage = clip_usage(&quota.api_key_prefix, &mut *transaction).await?;
+    match (quota.max_clips, quota.max_bytes) {
+        (Some(max_clips), _) if new_clip && usage.clips > max_clips => Err(
+            DataError::QuotaExceeded(format!("API key may store at most {} clips", max_clips)),
+        ),
+        (_, Some(max_bytes)) if usage.bytes > max_bytes => Err(DataError::QuotaExceeded(format!(
+            "API key may store at most {} bytes",
+            max_bytes
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Moves `data` to the blob store when one is configured and `data` is larger than its
+/// threshold. Returns the key to record in place of the data.
+async fn offload(data: &[u8]) -> Result<Option<String>> {
+    match Storage::current() {
+        Some(storage) if data.len() > storage.threshold => {
+            let key = Storage::new_key();
+            storage.store.put(&key, data.to_vec()).await?;
+            Ok(Some(key))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Clip content as written to a row. Large content is compressed into `zstd` when that
+/// pays off, and moved to the blob store when it is still large, leaving `content` empty.
+struct StoredContent<'a> {
+    content: &'a str,
+    zstd: Option<Vec<u8>>,
+    key: Option<String>,
+    /// Length of content not kept as text, which usage quotas cannot read from the row.
+    size: Option<i64>,
+    /// Content not kept as text, indexed for search in its place.
+    search_text: Option<&'a str>,
+}
+
+async fn store_content(content: &str) -> Result<StoredContent<'_>> {
+    let zstd = compression::compress(content);
+    let key = offload(zstd.as_deref().unwrap_or(content.as_bytes())).await?;
+    if zstd.is_none() && key.is_none() {
+        return Ok(StoredContent {
+            content,
+            zstd: None,
+            key: None,
+            size: None,
+            search_text: None,
+        });
+    }
+    Ok(StoredContent {
+        content: "",
+        zstd: if key.is_some() { None } else { zstd },
+        key,
+        size: Some(i64::try_from(content.len()).unwrap_or(i64::MAX)),
+        search_text: Some(content),
+    })
+}
+
+/// Reads data moved to the blob store under `key`.
+async fn load(key: &str) -> Result<Vec<u8>> {
+    let storage = Storage::current().ok_or(StorageError::NotConfigured)?;
+    Ok(storage.store.get(key).await?)
+}
+
+/// Fills in the content of a row from what was offloaded under `key`, if anything.
+/// Compressed content goes to `zstd` and is decompressed with the rest of the row.
+async fn restore_content(
+    content: &mut String,
+    zstd: &mut Option<Vec<u8>>,
+    key: Option<&str>,
+) -> Result<()> {
+    if let Some(key) = key {
+        let data = load(key).await?;
+        if compression::is_compressed(&data) {
+            *zstd = Some(data);
+        } else {
+            *content = String::from_utf8(data).map_err(|e| {
+                StorageError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Content of a clip stored compressed or in the blob store, as text.
+async fn stored_text(zstd: Option<Vec<u8>>, key: Option<&str>) -> Result<String> {
+    let mut content = String::new();
+    let mut zstd = zstd;
+    restore_content(&mut content, &mut zstd, key).await?;
+    match zstd {
+        Some(zstd) => compression::decompress(&zstd).map_err(|e| {
+            StorageError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)).into()
+        }),
+        None => Ok(content),
+    }
+}
+
+/// Every blob key still referred to by a clip, revision or attachment.
+pub async fn blob_keys(pool: &DatabasePool) -> Result<HashSet<String>> {
+    Ok(sqlx::query_scalar::<_, String>(
+        r#"
+            SELECT content_key FROM clips WHERE content_key IS NOT NULL
+            UNION SELECT content_key FROM clip_revisions WHERE content_key IS NOT NULL
+            UNION SELECT data_key FROM clip_attachments WHERE data_key IS NOT NULL
+        "#,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect())
+}
+
+pub async fn get_edit_token(shortcode: &Shortcode, pool: &DatabasePool) -> Result<Option<String>> {
+    Ok(get_clip(shortcode.clone(), pool).await?.edit_token)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::test::*;
+    use crate::data::*;
+    use crate::test::async_runtime;
+    use crate::Shortcode;
+    use chrono::Utc;
+    use sqlx::encode::IsNull::No;
+
+    fn model_get_clip(shortcode: &str) -> model::GetClip {
+        model::GetClip {
+            shortcode: shortcode.into(),
+        }
+    }
+
+    fn model_new_clip(shortcode: &str) -> model::NewClip {
+        use chrono::Utc;
+        model::NewClip {
+            clip_id: Dbid::new().into(),
+            content: format!("content for the clip '{}'", shortcode),
+            title: Some("Test".to_owned()),
+            shortcode: shortcode.into(),
+            posted: Utc::now().timestamp(),
+            expires: None,
+            password: None,
+            edit_token: None,
+            max_views: None,
+            api_key_prefix: None,
+            language: None,
+            format: "code".to_owned(),
+            attachment: None,
+            quota: None,
+        }
+    }
+
+    #[test]
+    fn clip_new_and_get() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        let clip =
+            rt.block_on(async move { query::new_clip(model_new_clip("1"), &pool.clone()).await });
+        assert!(clip.is_ok());
+        let clip = clip.unwrap();
+        assert_eq!(clip.shortcode, "1");
+        assert_eq!(
+            clip.content,
+            format!("content for the clip '{}'", clip.shortcode)
+        )
+    }
+
+    #[test]
+    fn clip_deleted_after_last_view() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let shortcode = Shortcode::from("1");
+
+        rt.block_on(async move {
+            let model = model::NewClip {
+                max_views: Some(2),
+                ..model_new_clip("1")
+            };
+            query::new_clip(model, pool).await.unwrap();
+            assert_eq!(query::consume_view(&shortcode, pool).await.unwrap(), 1);
+            assert_eq!(query::consume_view(&shortcode, pool).await.unwrap(), 0);
+            assert!(query::consume_view(&shortcode, pool).await.is_err());
+            assert!(query::get_clip(shortcode, pool).await.is_err());
+        });
+    }
+
+    #[test]
+    fn search_skips_protected_clips() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        rt.block_on(async move {
+            query::new_clip(model_new_clip("1"), pool).await.unwrap();
+            let protected = model::NewClip {
+                password: Some("secret".to_owned()),
+                ..model_new_clip("2")
+            };
+            query::new_clip(protected, pool).await.unwrap();
+
+            let results = query::search_clips("clip", 10, pool).await.unwrap();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].shortcode, "1");
+            let result =
+                crate::domain::clip::SearchResult::from(results.into_iter().next().unwrap());
+            assert!(!result.snippet.contains(model::MATCH_START));
+            assert!(!result.snippet.contains(model::MATCH_END));
+            let found = result.matches[0];
+            assert_eq!(&result.snippet[found.start..found.end], "clip");
+            assert!(query::search_clips("\"", 10, pool)
+                .await
+                .unwrap()
+                .is_empty());
+        });
+    }
+
+    #[test]
+    fn update_keeps_previous_revision() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let shortcode = Shortcode::from("1");
+
+        rt.block_on(async move {
+            query::new_clip(model_new_clip("1"), pool).await.unwrap();
+            let update = model::UpdateClip {
+                shortcode: "1".to_owned(),
+                content: "updated content".to_owned(),
+                title: None,
+                expires: None,
+                password: None,
+                language: None,
+                quota: None,
+            };
+            let clip = query::update_clip(update, pool).await.unwrap();
+            assert_eq!(clip.content, "updated content");
+
+            let revisions = query::get_revisions(&shortcode, pool).await.unwrap();
+            assert_eq!(revisions.len(), 1);
+            assert_eq!(revisions[0].revision, 1);
+            assert_eq!(revisions[0].content, "content for the clip '1'");
+        });
+    }
+
+    #[test]
+    fn api_key_keeps_metadata() {
+        use crate::domain::api_key::{ApiKeyInfo, Scope};
+        use crate::service::ask;
+        use crate::web::api::ApiKey;
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        rt.block_on(async move {
+            let key = ApiKey::default();
+            let req = ask::NewApiKey {
+                label: "ci".to_owned(),
+                expires: None,
+                scopes: "read".parse().unwrap(),
+            };
+            query::save_api_key(model::NewApiKey::new(&key, req), pool)
+                .await
+                .unwrap();
+
+            let stored = query::get_api_key(&key.prefix(), pool).await.unwrap();
+            assert_eq!(stored.digest(), key.digest().as_slice());
+            assert_ne!(stored.digest(), key.clone().into_inner().as_slice());
+            let info = ApiKeyInfo::from(stored);
+            assert_eq!(info.label, "ci");
+            assert!(info.allows(Scope::Read));
+            assert!(!info.allows(Scope::Write));
+            assert!(info.last_used_at.is_none());
+
+            query::touch_api_key(&key.prefix(), pool).await.unwrap();
+            let info = ApiKeyInfo::from(query::get_api_key(&key.prefix(), pool).await.unwrap());
+            assert!(info.last_used_at.is_some());
+        });
+    }
+
+    #[test]
+    fn legacy_api_keys_are_hashed() {
+        use crate::web::api::ApiKey;
+
+        #[cfg(feature = "sqlite")]
+        const INSERT_LEGACY: &str = "INSERT INTO legacy_api_keys (api_key) VALUES (?)";
+        #[cfg(feature = "postgres")]
+        const INSERT_LEGACY: &str = "INSERT INTO legacy_api_keys (api_key) VALUES ($1)";
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        rt.block_on(async move {
+            let key = ApiKey::default();
+            sqlx::query(INSERT_LEGACY)
+                .bind(key.clone().into_inner())
+                .execute(pool)
+                .await
+                .unwrap();
+            assert_eq!(query::upgrade_legacy_api_keys(pool).await.unwrap(), 1);
+
+            let stored = query::get_api_key(&key.prefix(), pool).await.unwrap();
+            assert_eq!(stored.digest(), key.digest().as_slice());
+            assert_eq!(query::upgrade_legacy_api_keys(pool).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn usage_counts_clips_of_one_key() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        rt.block_on(async move {
+            let prefix = Some("abc".to_owned());
+            query::new_clip(model_new_clip("1").with_api_key(prefix.clone()), pool)
+                .await
+                .unwrap();
+            query::new_clip(model_new_clip("2").with_api_key(prefix), pool)
+                .await
+                .unwrap();
+            query::new_clip(model_new_clip("3"), pool).await.unwrap();
+
+            let usage = query::clip_usage("abc", pool).await.unwrap();
+            assert_eq!(usage.clips, 2);
+            assert_eq!(usage.bytes, 2 * "content for the clip '1'".len() as i64);
+
+            let update = model::UpdateClip {
+                shortcode: "2".to_owned(),
+                content: "edited".to_owned(),
+                title: None,
+                expires: None,
+                password: None,
+                language: None,
+                quota: None,
+            };
+            query::update_clip(update, pool).await.unwrap();
+            let usage = query::clip_usage("abc", pool).await.unwrap();
+            assert_eq!(usage.clips, 2);
+            assert_eq!(
+                usage.bytes,
+                2 * "content for the clip '1'".len() as i64 + "edited".len() as i64
+            );
+        });
+    }
+
+    #[test]
+    fn views_are_grouped_by_period() {
+        use crate::domain::views::{AgentClass, ViewSource};
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        rt.block_on(async move {
+            query::new_clip(model_new_clip("1"), pool).await.unwrap();
+            let direct = ViewSource {
+                referrer_host: String::new(),
+                agent: AgentClass::Cli,
+            };
+            let day = 24 * 60 * 60;
+            let mut transaction = pool.begin().await.unwrap();
+            for (hour, views) in [(0, 2), (3600, 1), (day, 4)] {
+                query::record_views(&"1".into(), hour, &direct, views, &mut transaction)
+                    .await
+                    .unwrap();
+            }
+            query::record_views(&"missing".into(), 0, &direct, 1, &mut transaction)
+                .await
+                .unwrap();
+            transaction.commit().await.unwrap();
+
+            let hours = query::view_buckets(&"1".into(), 3600, 0, pool)
+                .await
+                .unwrap();
+            assert_eq!(hours.len(), 3);
+            let days = query::view_buckets(&"1".into(), day, 0, pool)
+                .await
+                .unwrap();
+            let days: Vec<_> = days.iter().map(|b| (b.bucket, b.views)).collect();
+            assert_eq!(days, vec![(0, 3), (day, 4)]);
+            let agents = query::view_counts(&"1".into(), day, true, pool)
+                .await
+                .unwrap();
+            assert_eq!(agents.len(), 1);
+            assert_eq!(agents[0].views, 4);
+        });
+    }
+
+    #[test]
+    fn attachments_are_stored_and_deleted_with_the_clip() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let shortcode = Shortcode::from("1");
+
+        rt.block_on(async move {
+            let model = model::NewClip {
+                attachment: Some(model::Attachment {
+                    name: "shot.png".to_owned(),
+                    mime: "image/png".to_owned(),
+                    size: 3,
+                    data: vec![1, 2, 3],
+                    data_key: None,
+                }),
+                ..model_new_clip("1")
+            };
+            let clip = query::new_clip(model.with_api_key(Some("abc".to_owned())), pool)
+                .await
+                .unwrap();
+            assert_eq!(clip.attachment_name.as_deref(), Some("shot.png"));
+            assert_eq!(clip.attachment_size, Some(3));
+            let usage = query::clip_usage("abc", pool).await.unwrap();
+            assert_eq!(usage.bytes, "content for the clip '1'".len() as i64 + 3);
+
+            let attachment = query::get_attachment(&shortcode, pool).await.unwrap();
+            assert_eq!(attachment.data, vec![1, 2, 3]);
+            query::delete_clip(&shortcode, pool).await.unwrap();
+            assert!(query::get_attachment(&shortcode, pool).await.is_err());
+        });
+    }
+
+    #[test]
+    fn quota_holds_for_concurrent_clips_of_one_key() {
+        use crate::data::DataError;
+        use crate::service::ask;
+        use crate::web::api::ApiKey;
+        use rocket::futures::future::join_all;
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        rt.block_on(async move {
+            let key = ApiKey::default();
+            let req = ask::NewApiKey {
+                label: "quota".to_owned(),
+                expires: None,
+                scopes: Default::default(),
+            };
+            query::save_api_key(model::NewApiKey::new(&key, req), pool)
+                .await
+                .unwrap();
+            let prefix = key.prefix();
+
+            let quota = model::Quota::new(prefix.clone(), Some(3), None);
+            let requests = (0..8).map(|i| {
+                let model = model_new_clip(&i.to_string())
+                    .with_api_key(Some(prefix.clone()))
+                    .with_quota(Some(quota.clone()));
+                query::new_clip(model, pool)
+            });
+            let mut created = vec![];
+            for result in join_all(requests).await {
+                match result {
+                    Ok(clip) => created.push(clip.shortcode),
+                    Err(DataError::QuotaExceeded(_)) => {}
+                    Err(e) => panic!("unexpected error: {}", e),
+                }
+            }
+            assert_eq!(created.len(), 3);
+            assert_eq!(query::clip_usage(&prefix, pool).await.unwrap().clips, 3);
+
+            // Edits keep the number of clips and only have to stay within the bytes.
+            let used = query::clip_usage(&prefix, pool).await.unwrap().bytes;
+            let quota = model::Quota::new(prefix.clone(), Some(3), Some(used as u64 + 10));
+            let update = |content: &str| model::UpdateClip {
+                shortcode: created[0].clone(),
+                content: content.to_owned(),
+                title: None,
+                expires: None,
+                password: None,
+                language: None,
+                quota: Some(quota.clone()),
+            };
+            query::update_clip(update("edited"), pool).await.unwrap();
+            assert!(matches!(
+                query::update_clip(update("edited again"), pool).await,
+                Err(DataError::QuotaExceeded(_))
+            ));
+            let clip = query::get_clip(created[0].clone(), pool).await.unwrap();
+            assert_eq!(clip.content, "edited");
+            assert_eq!(
+                query::clip_usage(&prefix, pool).await.unwrap().bytes,
+                used + "edited".len() as i64
+            );
+        });
+    }
+
+    #[test]
+    fn large_content_is_kept_in_the_blob_store() {
+        use crate::data::storage::{FileStore, Storage};
+        use std::sync::Arc;
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let shortcode = Shortcode::from("1");
+        let dir = std::env::temp_dir().join(format!("clipstash-query-{}", Storage::new_key()));
+        // Other tests store far less than the threshold, so they are not affected.
+        Storage::configure(Storage {
+            store: Arc::new(FileStore::new(&dir).unwrap()),
+            threshold: 4096,
+        });
+        let large = "stashed ".repeat(625);
+
+        rt.block_on(async move {
+            let model = model::NewClip {
+                content: large.clone(),
+                attachment: Some(model::Attachment {
+                    name: "large.bin".to_owned(),
+                    mime: "application/octet-stream".to_owned(),
+                    size: 5000,
+                    data: vec![7; 5000],
+                    data_key: None,
+                }),
+                ..model_new_clip("1")
+            };
+            let clip = query::new_clip(model.with_api_key(Some("abc".to_owned())), pool)
+                .await
+                .unwrap();
+            assert_eq!(clip.content, large);
+            assert!(clip.content_key.is_some());
+            let usage = query::clip_usage("abc", pool).await.unwrap();
+            assert_eq!(usage.bytes, 10000);
+            let attachment = query::get_attachment(&shortcode, pool).await.unwrap();
+            assert_eq!(attachment.data, vec![7; 5000]);
+            let results = query::search_clips("stashed", 10, pool).await.unwrap();
+            assert_eq!(results.len(), 1);
+            assert!(results[0].snippet.contains("stashed"));
+
+            let update = model::UpdateClip {
+                shortcode: "1".to_owned(),
+                content: "small now".to_owned(),
+                title: None,
+                expires: None,
+                password: None,
+                language: None,
+                quota: None,
+            };
+            let clip = query::update_clip(update, pool).await.unwrap();
+            assert_eq!(clip.content, "small now");
+            assert!(clip.content_key.is_none());
+            let revision = query::get_revision(&shortcode, 1, pool).await.unwrap();
+            assert_eq!(revision.content, large);
+            let usage = query::clip_usage("abc", pool).await.unwrap();
+            assert_eq!(usage.bytes, 10000 + "small now".len() as i64);
+            assert!(query::search_clips("stashed", 10, pool)
+                .await
+                .unwrap()
+                .is_empty());
+            assert_eq!(
+                query::search_clips("small", 10, pool).await.unwrap().len(),
+                1
+            );
+            assert_eq!(query::blob_keys(pool).await.unwrap().len(), 2);
+
+            query::delete_clip(&shortcode, pool).await.unwrap();
+            assert!(query::blob_keys(pool).await.unwrap().is_empty());
+        });
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn large_content_is_stored_compressed() {
+        use std::convert::TryFrom;
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let shortcode = Shortcode::from("1");
+        let log = "GET /clip/abc 200 OK\n".repeat(1000);
+
+        rt.block_on(async move {
+            let model = model::NewClip {
+                content: log.clone(),
+                ..model_new_clip("1")
+            };
+            let clip = query::new_clip(model.with_api_key(Some("abc".to_owned())), pool)
+                .await
+                .unwrap();
+            assert!(clip.content.is_empty());
+            assert!(clip.content_zstd.as_ref().unwrap().len() < log.len() / 10);
+            let usage = query::clip_usage("abc", pool).await.unwrap();
+            assert_eq!(usage.bytes, log.len() as i64);
+            let clip = crate::Clip::try_from(clip).unwrap();
+            assert_eq!(clip.content.as_str(), log);
+
+            let update = model::UpdateClip {
+                shortcode: "1".to_owned(),
+                content: "small now".to_owned(),
+                title: None,
+                expires: None,
+                password: None,
+                language: None,
+                quota: None,
+            };
+            let clip = query::update_clip(update, pool).await.unwrap();
+            assert!(clip.content_zstd.is_none());
+            let revision = query::get_revision(&shortcode, 1, pool).await.unwrap();
+            let revision = crate::domain::clip::Revision::try_from(revision).unwrap();
+            assert_eq!(revision.content.as_str(), log);
+        });
+    }
+
+    #[test]
+    fn compressed_content_is_searchable() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let log = "GET /clip/abc 200 OK teapot\n".repeat(1000);
+
+        rt.block_on(async move {
+            let model = model::NewClip {
+                content: log.clone(),
+                ..model_new_clip("1")
+            };
+            let clip = query::new_clip(model, pool).await.unwrap();
+            assert!(clip.content_zstd.is_some());
+            let results = query::search_clips("teapot", 10, pool).await.unwrap();
+            assert_eq!(results.len(), 1);
+            assert!(results[0].snippet.contains("teapot"));
+
+            let update = model::UpdateClip {
+                shortcode: "1".to_owned(),
+                content: "PUT /clip/abc 418 kettle\n".repeat(1000),
+                title: None,
+                expires: None,
+                password: None,
+                language: None,
+                quota: None,
+            };
+            query::update_clip(update, pool).await.unwrap();
+            assert!(query::search_clips("teapot", 10, pool)
+                .await
+                .unwrap()
+                .is_empty());
+            assert_eq!(
+                query::search_clips("kettle", 10, pool).await.unwrap().len(),
+                1
+            );
+
+            // Clips stored before their text was indexed are picked up after migrating.
+            #[cfg(feature = "sqlite")]
+            let forget = "UPDATE clips_fts SET content = ''";
+            #[cfg(feature = "postgres")]
+            let forget = "UPDATE clips SET search_text = NULL";
+            sqlx::query(forget).execute(pool).await.unwrap();
+            assert!(query::search_clips("kettle", 10, pool)
+                .await
+                .unwrap()
+                .is_empty());
+            assert_eq!(query::index_stored_content(pool).await.unwrap(), 1);
+            assert_eq!(
+                query::search_clips("kettle", 10, pool).await.unwrap().len(),
+                1
+            );
+        });
+    }
+
+    #[test]
+    fn duplicate_shortcode_is_reported() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        rt.block_on(async move {
+            query::new_clip(model_new_clip("1"), pool).await.unwrap();
+            let taken = query::new_clip(model_new_clip("1"), pool).await;
+            assert!(matches!(taken, Err(DataError::ShortcodeTaken)));
+        });
+    }
+}
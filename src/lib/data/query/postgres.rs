@@ -0,0 +1,653 @@
+use super::{
+    check_quota, load, offload, restore_content, store_content, stored_text, Result,
+    RevocationStatus,
+};
+use crate::data::{model, Backend, DatabasePool, Transaction};
+use crate::domain::api_key;
+use crate::domain::views::ViewSource;
+use crate::web::api::ApiKey;
+use crate::Shortcode;
+
+pub async fn get_clip<M: Into<model::GetClip>>(
+    model: M,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let model = model.into();
+    let shortcode = model.shortcode.as_str();
+    let mut clip = sqlx::query_as!(
+        model::Clip,
+        r#"
+            SELECT
+                clips.clip_id, clips.shortcode, clips.content, clips.content_key, clips.content_zstd,
+                clips.title,
+                clips.posted, clips.expires, clips.password, clips.hits, clips.edit_token,
+                clips.max_views, clips.language, clips.format,
+                clip_attachments.name AS "attachment_name?",
+                clip_attachments.mime AS "attachment_mime?",
+                clip_attachments.size AS "attachment_size?"
+            FROM clips LEFT JOIN clip_attachments USING (shortcode)
+            WHERE clips.shortcode = $1
+        "#,
+        shortcode
+    )
+    .fetch_one(pool)
+    .await?;
+    restore_content(
+        &mut clip.content,
+        &mut clip.content_zstd,
+        clip.content_key.as_deref(),
+    )
+    .await?;
+    Ok(clip)
+}
+
+pub async fn new_clip<M: Into<model::NewClip>>(
+    model: M,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let model = model.into();
+    let stored = store_content(&model.content).await?;
+    let attachment_key = match &model.attachment {
+        Some(attachment) => offload(&attachment.data).await?,
+        None => None,
+    };
+    let mut transaction = pool.begin().await?;
+    if let Some(quota) = &model.quota {
+        lock_api_key(&quota.api_key_prefix, &mut transaction).await?;
+    }
+    let _ = sqlx::query!(
+        r#"INSERT INTO clips(
+            clip_id,
+            shortcode,
+            content,
+            title,
+            posted,
+            expires,
+            password,
+            hits,
+            edit_token,
+            max_views,
+            api_key_prefix,
+            language,
+            format,
+            content_key,
+            content_size,
+            content_zstd,
+            search_text)
+        VALUES (
+            $1, $2, $3, $4,
+            to_timestamp($5::BIGINT) AT TIME ZONE 'UTC',
+            to_timestamp($6::BIGINT) AT TIME ZONE 'UTC',
+            $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)"#,
+        model.clip_id,
+        model.shortcode,
+        stored.content,
+        model.title,
+        model.posted,
+        model.expires,
+        model.password,
+        0,
+        model.edit_token,
+        model.max_views,
+        model.api_key_prefix,
+        model.language,
+        model.format,
+        stored.key,
+        stored.size,
+        stored.zstd,
+        stored.search_text
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(super::shortcode_taken)?;
+    if let Some(attachment) = &model.attachment {
+        let data: &[u8] = match attachment_key {
+            Some(_) => &[],
+            None => &attachment.data,
+        };
+        let _ = sqlx::query!(
+            r#"INSERT INTO clip_attachments(shortcode, name, mime, size, data, created, data_key)
+            VALUES ($1, $2, $3, $4, $5, to_timestamp($6::BIGINT) AT TIME ZONE 'UTC', $7)"#,
+            model.shortcode,
+            attachment.name,
+            attachment.mime,
+            attachment.size,
+            data,
+            model.posted,
+            attachment_key
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    if let Some(quota) = &model.quota {
+        check_quota(quota, true, &mut transaction).await?;
+    }
+    transaction.commit().await?;
+    get_clip(model.shortcode, pool).await
+}
+
+/// Indexes clips stored compressed or in the blob store before their text went into the
+/// search index. Returns the number of clips indexed.
+pub async fn index_stored_content(pool: &DatabasePool) -> Result<u64> {
+    let unindexed = sqlx::query!(
+        r#"
+            SELECT shortcode, content_zstd, content_key FROM clips
+            WHERE search_text IS NULL AND (content_zstd IS NOT NULL OR content_key IS NOT NULL)
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut indexed = 0;
+    for row in unindexed {
+        let text = stored_text(row.content_zstd, row.content_key.as_deref()).await?;
+        sqlx::query!(
+            "UPDATE clips SET search_text = $1 WHERE shortcode = $2",
+            text,
+            row.shortcode
+        )
+        .execute(pool)
+        .await?;
+        indexed += 1;
+    }
+    Ok(indexed)
+}
+
+pub async fn get_attachment(
+    shortcode: &Shortcode,
+    pool: &DatabasePool,
+) -> Result<model::Attachment> {
+    let shortcode = shortcode.as_str();
+    let mut attachment = sqlx::query_as!(
+        model::Attachment,
+        "SELECT name, mime, size, data, data_key FROM clip_attachments WHERE shortcode = $1",
+        shortcode
+    )
+    .fetch_one(pool)
+    .await?;
+    if let Some(key) = &attachment.data_key {
+        attachment.data = load(key).await?;
+    }
+    Ok(attachment)
+}
+
+/// Clips of a key and the bytes they take up, counting attachments and every revision.
+pub async fn clip_usage<'e, E: sqlx::Executor<'e, Database = Backend>>(
+    api_key_prefix: &str,
+    executor: E,
+) -> Result<model::KeyUsage> {
+    Ok(sqlx::query_as!(
+        model::KeyUsage,
+        r#"
+            SELECT
+                COUNT(*) AS "clips!",
+                COALESCE(SUM(
+                    COALESCE(clips.content_size, octet_length(clips.content))
+                    + COALESCE(clip_attachments.size, 0)
+                    + (
+                        SELECT COALESCE(SUM(COALESCE(
+                            clip_revisions.content_size,
+                            octet_length(clip_revisions.content)
+                        )), 0)
+                        FROM clip_revisions WHERE clip_revisions.shortcode = clips.shortcode
+                    )
+                ), 0)::BIGINT AS "bytes!"
+            FROM clips LEFT JOIN clip_attachments USING (shortcode)
+            WHERE api_key_prefix = $1
+        "#,
+        api_key_prefix
+    )
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Locks the row of the key until `transaction` ends, so the quota of the key is checked
+/// by one request at a time.
+async fn lock_api_key(api_key_prefix: &str, transaction: &mut Transaction<'_>) -> Result<()> {
+    sqlx::query!(
+        "SELECT prefix FROM api_keys WHERE prefix = $1 FOR UPDATE",
+        api_key_prefix
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(())
+}
+
+pub async fn update_clip<M: Into<model::UpdateClip>>(
+    model: M,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let model = model.into();
+    let stored = store_content(&model.content).await?;
+    let mut transaction = pool.begin().await?;
+    if let Some(quota) = &model.quota {
+        lock_api_key(&quota.api_key_prefix, &mut transaction).await?;
+    }
+    let _ = sqlx::query!(
+        r#"
+            INSERT INTO clip_revisions(
+                shortcode, revision, content, title, created, content_key, content_zstd,
+                content_size
+            )
+            SELECT
+                shortcode,
+                (SELECT COALESCE(MAX(revision), 0) + 1 FROM clip_revisions WHERE shortcode = $1),
+                content,
+                title,
+                now() AT TIME ZONE 'UTC',
+                content_key,
+                content_zstd,
+                content_size
+            FROM clips WHERE shortcode = $1
+        "#,
+        model.shortcode
+    )
+    .execute(&mut transaction)
+    .await?;
+    let _ = sqlx::query!(
+        r#"
+            UPDATE clips SET
+                content = $1,
+                expires = to_timestamp($2::BIGINT) AT TIME ZONE 'UTC',
+                password = $3,
+                title = $4,
+                language = $5,
+                content_key = $6,
+                content_size = $7,
+                content_zstd = $8,
+                search_text = $9
+            WHERE shortcode = $10
+        "#,
+        stored.content,
+        model.expires,
+        model.password,
+        model.title,
+        model.language,
+        stored.key,
+        stored.size,
+        stored.zstd,
+        stored.search_text,
+        model.shortcode
+    )
+    .execute(&mut transaction)
+    .await?;
+    if let Some(quota) = &model.quota {
+        check_quota(quota, false, &mut transaction).await?;
+    }
+    transaction.commit().await?;
+    get_clip(model.shortcode, pool).await
+}
+
+pub async fn get_revisions(
+    shortcode: &Shortcode,
+    pool: &DatabasePool,
+) -> Result<Vec<model::Revision>> {
+    let shortcode = shortcode.as_str();
+    let mut revisions = sqlx::query_as!(
+        model::Revision,
+        r#"
+            SELECT shortcode, revision, content, title, created, content_key, content_zstd
+            FROM clip_revisions WHERE shortcode = $1 ORDER BY revision DESC
+        "#,
+        shortcode
+    )
+    .fetch_all(pool)
+    .await?;
+    for revision in revisions.iter_mut() {
+        restore_content(
+            &mut revision.content,
+            &mut revision.content_zstd,
+            revision.content_key.as_deref(),
+        )
+        .await?;
+    }
+    Ok(revisions)
+}
+
+pub async fn get_revision(
+    shortcode: &Shortcode,
+    revision: u32,
+    pool: &DatabasePool,
+) -> Result<model::Revision> {
+    let shortcode = shortcode.as_str();
+    let mut revision = sqlx::query_as!(
+        model::Revision,
+        r#"
+            SELECT shortcode, revision, content, title, created, content_key, content_zstd
+            FROM clip_revisions WHERE shortcode = $1 AND revision = $2
+        "#,
+        shortcode,
+        i64::from(revision)
+    )
+    .fetch_one(pool)
+    .await?;
+    restore_content(
+        &mut revision.content,
+        &mut revision.content_zstd,
+        revision.content_key.as_deref(),
+    )
+    .await?;
+    Ok(revision)
+}
+
+pub async fn update_clip_password(
+    shortcode: &Shortcode,
+    password: Option<String>,
+    pool: &DatabasePool,
+) -> Result<()> {
+    let shortcode = shortcode.as_str();
+    Ok(sqlx::query!(
+        "UPDATE clips SET password = $1 WHERE shortcode = $2",
+        password,
+        shortcode,
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?)
+}
+
+/// Uses up one view of a clip created with a view limit, deleting it after the last one.
+///
+/// Returns the number of views left, or `RowNotFound` when none were left to use.
+pub async fn consume_view(shortcode: &Shortcode, pool: &DatabasePool) -> Result<i64> {
+    let shortcode = shortcode.as_str();
+    let mut transaction = pool.begin().await?;
+    let views_left = sqlx::query!(
+        r#"
+            UPDATE clips SET max_views = max_views - 1
+            WHERE shortcode = $1 AND max_views > 0
+            RETURNING max_views
+        "#,
+        shortcode
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .ok_or(sqlx::Error::RowNotFound)?
+    .max_views
+    .unwrap_or_default();
+    if views_left <= 0 {
+        sqlx::query!("DELETE FROM clips WHERE shortcode = $1", shortcode)
+            .execute(&mut transaction)
+            .await?;
+    }
+    transaction.commit().await?;
+    Ok(views_left)
+}
+
+pub async fn increase_hit_count(
+    shortcode: &Shortcode,
+    hits: u32,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    let shortcode = shortcode.as_str();
+    Ok(sqlx::query!(
+        "UPDATE clips SET hits = hits + $1 WHERE shortcode = $2",
+        i64::from(hits),
+        shortcode,
+    )
+    .execute(transaction)
+    .await
+    .map(|_| ())?)
+}
+
+/// Adds `views` to the bucket of a clip, ignoring clips deleted in the meantime.
+pub async fn record_views(
+    shortcode: &Shortcode,
+    bucket: i64,
+    source: &ViewSource,
+    views: u32,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    let shortcode = shortcode.as_str();
+    let agent = source.agent.as_str();
+    Ok(sqlx::query!(
+        r#"
+            INSERT INTO clip_views (shortcode, bucket, referrer_host, agent, views)
+            SELECT $1, to_timestamp($2::BIGINT) AT TIME ZONE 'UTC', $3, $4, $5
+            WHERE EXISTS (SELECT 1 FROM clips WHERE shortcode = $1)
+            ON CONFLICT (shortcode, bucket, referrer_host, agent)
+            DO UPDATE SET views = clip_views.views + excluded.views
+        "#,
+        shortcode,
+        bucket,
+        source.referrer_host,
+        agent,
+        i64::from(views)
+    )
+    .execute(transaction)
+    .await
+    .map(|_| ())?)
+}
+
+/// Views of a clip since `since`, summed into buckets of `period` seconds.
+pub async fn view_buckets(
+    shortcode: &Shortcode,
+    period: i64,
+    since: i64,
+    pool: &DatabasePool,
+) -> Result<Vec<model::ViewBucket>> {
+    Ok(sqlx::query_as::<_, model::ViewBucket>(
+        r#"
+            SELECT EXTRACT(EPOCH FROM bucket)::BIGINT / $1 * $1 AS bucket, SUM(views)::BIGINT AS views
+            FROM clip_views
+            WHERE shortcode = $2 AND bucket >= to_timestamp($3) AT TIME ZONE 'UTC'
+            GROUP BY 1 ORDER BY 1
+        "#,
+    )
+    .bind(period)
+    .bind(shortcode.as_str())
+    .bind(since)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Views of a clip since `since` per referrer host, or per agent class when `by_agent`.
+pub async fn view_counts(
+    shortcode: &Shortcode,
+    since: i64,
+    by_agent: bool,
+    pool: &DatabasePool,
+) -> Result<Vec<model::ViewCount>> {
+    let column = if by_agent { "agent" } else { "referrer_host" };
+    Ok(sqlx::query_as::<_, model::ViewCount>(&format!(
+        r#"
+            SELECT {column} AS name, SUM(views)::BIGINT AS views
+            FROM clip_views
+            WHERE shortcode = $1 AND bucket >= to_timestamp($2) AT TIME ZONE 'UTC'
+            GROUP BY {column} ORDER BY views DESC LIMIT 10
+        "#,
+        column = column
+    ))
+    .bind(shortcode.as_str())
+    .bind(since)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn save_api_key(model: model::NewApiKey, pool: &DatabasePool) -> Result<()> {
+    sqlx::query!(
+        r#"
+            INSERT INTO api_keys (prefix, digest, label, created, expires, scopes)
+            VALUES ($1, $2, $3, to_timestamp($4::BIGINT) AT TIME ZONE 'UTC', to_timestamp($5::BIGINT) AT TIME ZONE 'UTC', $6)
+        "#,
+        model.prefix,
+        model.digest,
+        model.label,
+        model.created,
+        model.expires,
+        model.scopes
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+pub async fn get_api_key(prefix: &str, pool: &DatabasePool) -> Result<model::ApiKey> {
+    Ok(sqlx::query_as!(
+        model::ApiKey,
+        r#"
+            SELECT prefix, digest, label, created, expires, last_used_at, scopes
+            FROM api_keys WHERE prefix = $1
+        "#,
+        prefix
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn touch_api_key(prefix: &str, pool: &DatabasePool) -> Result<()> {
+    Ok(sqlx::query!(
+        "UPDATE api_keys SET last_used_at = now() AT TIME ZONE 'UTC' WHERE prefix = $1",
+        prefix
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?)
+}
+
+pub async fn revoke_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<RevocationStatus> {
+    let prefix = api_key.prefix();
+    let digest = api_key.digest();
+    Ok(sqlx::query!(
+        "DELETE FROM api_keys WHERE prefix = $1 AND digest = $2",
+        prefix,
+        digest
+    )
+    .execute(pool)
+    .await
+    .map(|res| match res.rows_affected() {
+        0 => RevocationStatus::NotFound,
+        _ => RevocationStatus::Revoked,
+    })?)
+}
+
+pub async fn list_api_keys(pool: &DatabasePool) -> Result<Vec<model::ApiKey>> {
+    Ok(sqlx::query_as!(
+        model::ApiKey,
+        r#"
+            SELECT prefix, digest, label, created, expires, last_used_at, scopes
+            FROM api_keys ORDER BY created
+        "#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn revoke_api_key_by_prefix(
+    prefix: &str,
+    pool: &DatabasePool,
+) -> Result<RevocationStatus> {
+    Ok(
+        sqlx::query!("DELETE FROM api_keys WHERE prefix = $1", prefix)
+            .execute(pool)
+            .await
+            .map(|res| match res.rows_affected() {
+                0 => RevocationStatus::NotFound,
+                _ => RevocationStatus::Revoked,
+            })?,
+    )
+}
+
+/// Hashes keys stored in plain text before digests were introduced and removes the
+/// raw copies. Returns the number of keys moved.
+pub async fn upgrade_legacy_api_keys(pool: &DatabasePool) -> Result<u64> {
+    let mut transaction = pool.begin().await?;
+    let legacy = sqlx::query!("SELECT api_key FROM legacy_api_keys")
+        .fetch_all(&mut transaction)
+        .await?;
+    let mut moved = 0;
+    for row in legacy {
+        let key = row.api_key;
+        let prefix = api_key::prefix(&key);
+        let digest = api_key::digest(&key);
+        sqlx::query!(
+            r#"
+                INSERT INTO api_keys (prefix, digest, label, created, expires, last_used_at, scopes)
+                SELECT $1, $2, label, created, expires, last_used_at, scopes
+                FROM legacy_api_keys WHERE api_key = $3
+            "#,
+            prefix,
+            digest,
+            key
+        )
+        .execute(&mut transaction)
+        .await?;
+        moved += 1;
+    }
+    sqlx::query!("DELETE FROM legacy_api_keys")
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(moved)
+}
+
+/// Turns free text into a `tsquery`: every word must match, the last one as a prefix.
+fn ts_query(raw: &str) -> String {
+    let mut terms = raw
+        .split_whitespace()
+        .map(|term| format!("'{}'", term.replace('\\', "\\\\").replace('\'', "''")))
+        .collect::<Vec<_>>();
+    if let Some(last) = terms.last_mut() {
+        last.push_str(":*");
+    }
+    terms.join(" & ")
+}
+
+/// Full-text search over clip titles and content, best matches first.
+///
+/// Password protected, view limited and expired clips are never returned.
+pub async fn search_clips(
+    query: &str,
+    limit: u32,
+    pool: &DatabasePool,
+) -> Result<Vec<model::SearchResult>> {
+    let query = ts_query(query);
+    if query.is_empty() {
+        return Ok(vec![]);
+    }
+    let headline = format!(
+        "StartSel={}, StopSel={}, MaxWords=24, MinWords=8",
+        model::MATCH_START,
+        model::MATCH_END
+    );
+    Ok(sqlx::query_as::<_, model::SearchResult>(
+        r#"
+            SELECT
+                shortcode,
+                title,
+                ts_headline('simple', coalesce(search_text, content), query, $1) AS snippet,
+                -ts_rank(search, query)::FLOAT8 AS rank
+            FROM clips, to_tsquery('simple', $2) AS query
+            WHERE search @@ query
+                AND password IS NULL
+                AND max_views IS NULL
+                AND (expires IS NULL OR expires > now() AT TIME ZONE 'UTC')
+            ORDER BY rank
+            LIMIT $3
+        "#,
+    )
+    .bind(headline)
+    .bind(query)
+    .bind(i64::from(limit))
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn delete_clip(shortcode: &Shortcode, pool: &DatabasePool) -> Result<u64> {
+    let shortcode = shortcode.as_str();
+    Ok(
+        sqlx::query!("DELETE FROM clips WHERE shortcode = $1", shortcode)
+            .execute(pool)
+            .await?
+            .rows_affected(),
+    )
+}
+
+pub async fn delete_expired(pool: &DatabasePool) -> Result<u64> {
+    Ok(
+        sqlx::query!("DELETE FROM clips WHERE now() AT TIME ZONE 'UTC' > expires")
+            .execute(pool)
+            .await?
+            .rows_affected(),
+    )
+}
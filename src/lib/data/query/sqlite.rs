@@ -0,0 +1,670 @@
+use super::{
+    check_quota, load, offload, restore_content, store_content, stored_text, Result,
+    RevocationStatus,
+};
+use crate::data::{model, Backend, DatabasePool, Transaction};
+use crate::domain::api_key;
+use crate::domain::views::ViewSource;
+use crate::web::api::ApiKey;
+use crate::Shortcode;
+
+pub async fn get_clip<M: Into<model::GetClip>>(
+    model: M,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let model = model.into();
+    let shortcode = model.shortcode.as_str();
+    let mut clip = sqlx::query_as!(
+        model::Clip,
+        r#"
+            SELECT
+                clips.clip_id, clips.shortcode, clips.content, clips.content_key, clips.content_zstd,
+                clips.title,
+                clips.posted, clips.expires, clips.password, clips.hits, clips.edit_token,
+                clips.max_views, clips.language, clips.format,
+                clip_attachments.name AS "attachment_name?",
+                clip_attachments.mime AS "attachment_mime?",
+                clip_attachments.size AS "attachment_size?"
+            FROM clips LEFT JOIN clip_attachments USING (shortcode)
+            WHERE clips.shortcode = ?
+        "#,
+        shortcode
+    )
+    .fetch_one(pool)
+    .await?;
+    restore_content(
+        &mut clip.content,
+        &mut clip.content_zstd,
+        clip.content_key.as_deref(),
+    )
+    .await?;
+    Ok(clip)
+}
+
+pub async fn new_clip<M: Into<model::NewClip>>(
+    model: M,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let model = model.into();
+    let stored = store_content(&model.content).await?;
+    let attachment_key = match &model.attachment {
+        Some(attachment) => offload(&attachment.data).await?,
+        None => None,
+    };
+    let mut transaction = pool.begin().await?;
+    if let Some(quota) = &model.quota {
+        lock_api_key(&quota.api_key_prefix, &mut transaction).await?;
+    }
+    let _ = sqlx::query!(
+        r#"INSERT INTO clips(
+            clip_id,
+            shortcode,
+            content,
+            title,
+            posted,
+            expires,
+            password,
+            hits,
+            edit_token,
+            max_views,
+            api_key_prefix,
+            language,
+            format,
+            content_key,
+            content_size,
+            content_zstd)
+        VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)"#,
+        model.clip_id,
+        model.shortcode,
+        stored.content,
+        model.title,
+        model.posted,
+        model.expires,
+        model.password,
+        0,
+        model.edit_token,
+        model.max_views,
+        model.api_key_prefix,
+        model.language,
+        model.format,
+        stored.key,
+        stored.size,
+        stored.zstd
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(super::shortcode_taken)?;
+    if let Some(text) = stored.search_text {
+        index_content(&model.shortcode, text, &mut transaction).await?;
+    }
+    if let Some(attachment) = &model.attachment {
+        let data: &[u8] = match attachment_key {
+            Some(_) => &[],
+            None => &attachment.data,
+        };
+        let _ = sqlx::query!(
+            r#"INSERT INTO clip_attachments(shortcode, name, mime, size, data, created, data_key)
+            VALUES (?,?,?,?,?,?,?)"#,
+            model.shortcode,
+            attachment.name,
+            attachment.mime,
+            attachment.size,
+            data,
+            model.posted,
+            attachment_key
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    if let Some(quota) = &model.quota {
+        check_quota(quota, true, &mut transaction).await?;
+    }
+    transaction.commit().await?;
+    get_clip(model.shortcode, pool).await
+}
+
+/// Puts the text of content stored compressed or in the blob store into the search index,
+/// whose triggers only see the empty `content` column.
+async fn index_content(
+    shortcode: &str,
+    text: &str,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    sqlx::query("UPDATE clips_fts SET content = ? WHERE shortcode = ?")
+        .bind(text)
+        .bind(shortcode)
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Indexes clips stored compressed or in the blob store before their text went into the
+/// search index. Returns the number of clips indexed.
+pub async fn index_stored_content(pool: &DatabasePool) -> Result<u64> {
+    let unindexed = sqlx::query_as::<_, (String, Option<Vec<u8>>, Option<String>)>(
+        r#"
+            SELECT clips.shortcode, clips.content_zstd, clips.content_key
+            FROM clips JOIN clips_fts ON clips_fts.shortcode = clips.shortcode
+            WHERE (clips.content_zstd IS NOT NULL OR clips.content_key IS NOT NULL)
+                AND clips_fts.content = ''
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut indexed = 0;
+    for (shortcode, zstd, key) in unindexed {
+        let text = stored_text(zstd, key.as_deref()).await?;
+        let mut transaction = pool.begin().await?;
+        index_content(&shortcode, &text, &mut transaction).await?;
+        transaction.commit().await?;
+        indexed += 1;
+    }
+    Ok(indexed)
+}
+
+pub async fn get_attachment(
+    shortcode: &Shortcode,
+    pool: &DatabasePool,
+) -> Result<model::Attachment> {
+    let shortcode = shortcode.as_str();
+    let mut attachment = sqlx::query_as!(
+        model::Attachment,
+        "SELECT name, mime, size, data, data_key FROM clip_attachments WHERE shortcode = ?",
+        shortcode
+    )
+    .fetch_one(pool)
+    .await?;
+    if let Some(key) = &attachment.data_key {
+        attachment.data = load(key).await?;
+    }
+    Ok(attachment)
+}
+
+/// Clips of a key and the bytes they take up, counting attachments and every revision.
+pub async fn clip_usage<'e, E: sqlx::Executor<'e, Database = Backend>>(
+    api_key_prefix: &str,
+    executor: E,
+) -> Result<model::KeyUsage> {
+    Ok(sqlx::query_as!(
+        model::KeyUsage,
+        r#"
+            SELECT
+                COUNT(*) AS "clips!: i64",
+                COALESCE(SUM(
+                    COALESCE(clips.content_size, LENGTH(CAST(clips.content AS BLOB)))
+                    + COALESCE(clip_attachments.size, 0)
+                    + (
+                        SELECT COALESCE(SUM(COALESCE(
+                            clip_revisions.content_size,
+                            LENGTH(CAST(clip_revisions.content AS BLOB))
+                        )), 0)
+                        FROM clip_revisions WHERE clip_revisions.shortcode = clips.shortcode
+                    )
+                ), 0) AS "bytes!: i64"
+            FROM clips LEFT JOIN clip_attachments USING (shortcode)
+            WHERE api_key_prefix = ?
+        "#,
+        api_key_prefix
+    )
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Takes the write lock of the database for `transaction` before it reads anything, so
+/// the quota of the key is checked by one writer at a time.
+async fn lock_api_key(api_key_prefix: &str, transaction: &mut Transaction<'_>) -> Result<()> {
+    sqlx::query!(
+        "UPDATE api_keys SET prefix = prefix WHERE prefix = ?",
+        api_key_prefix
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+pub async fn update_clip<M: Into<model::UpdateClip>>(
+    model: M,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let model = model.into();
+    let stored = store_content(&model.content).await?;
+    let mut transaction = pool.begin().await?;
+    if let Some(quota) = &model.quota {
+        lock_api_key(&quota.api_key_prefix, &mut transaction).await?;
+    }
+    let _ = sqlx::query!(
+        r#"
+            INSERT INTO clip_revisions(
+                shortcode, revision, content, title, created, content_key, content_zstd,
+                content_size
+            )
+            SELECT
+                shortcode,
+                (SELECT COALESCE(MAX(revision), 0) + 1 FROM clip_revisions WHERE shortcode = ?),
+                content,
+                title,
+                strftime('%s', 'now'),
+                content_key,
+                content_zstd,
+                content_size
+            FROM clips WHERE shortcode = ?
+        "#,
+        model.shortcode,
+        model.shortcode
+    )
+    .execute(&mut transaction)
+    .await?;
+    let _ = sqlx::query!(
+        r#"
+            UPDATE clips SET
+                content = ?,
+                expires = ?,
+                password = ?,
+                title = ?,
+                language = ?,
+                content_key = ?,
+                content_size = ?,
+                content_zstd = ?
+            WHERE shortcode = ?
+        "#,
+        stored.content,
+        model.expires,
+        model.password,
+        model.title,
+        model.language,
+        stored.key,
+        stored.size,
+        stored.zstd,
+        model.shortcode
+    )
+    .execute(&mut transaction)
+    .await?;
+    if let Some(text) = stored.search_text {
+        index_content(&model.shortcode, text, &mut transaction).await?;
+    }
+    if let Some(quota) = &model.quota {
+        check_quota(quota, false, &mut transaction).await?;
+    }
+    transaction.commit().await?;
+    get_clip(model.shortcode, pool).await
+}
+
+pub async fn get_revisions(
+    shortcode: &Shortcode,
+    pool: &DatabasePool,
+) -> Result<Vec<model::Revision>> {
+    let shortcode = shortcode.as_str();
+    let mut revisions = sqlx::query_as!(
+        model::Revision,
+        r#"
+            SELECT shortcode, revision, content, title, created, content_key, content_zstd
+            FROM clip_revisions WHERE shortcode = ? ORDER BY revision DESC
+        "#,
+        shortcode
+    )
+    .fetch_all(pool)
+    .await?;
+    for revision in revisions.iter_mut() {
+        restore_content(
+            &mut revision.content,
+            &mut revision.content_zstd,
+            revision.content_key.as_deref(),
+        )
+        .await?;
+    }
+    Ok(revisions)
+}
+
+pub async fn get_revision(
+    shortcode: &Shortcode,
+    revision: u32,
+    pool: &DatabasePool,
+) -> Result<model::Revision> {
+    let shortcode = shortcode.as_str();
+    let mut revision = sqlx::query_as!(
+        model::Revision,
+        r#"
+            SELECT shortcode, revision, content, title, created, content_key, content_zstd
+            FROM clip_revisions WHERE shortcode = ? AND revision = ?
+        "#,
+        shortcode,
+        revision
+    )
+    .fetch_one(pool)
+    .await?;
+    restore_content(
+        &mut revision.content,
+        &mut revision.content_zstd,
+        revision.content_key.as_deref(),
+    )
+    .await?;
+    Ok(revision)
+}
+
+pub async fn update_clip_password(
+    shortcode: &Shortcode,
+    password: Option<String>,
+    pool: &DatabasePool,
+) -> Result<()> {
+    let shortcode = shortcode.as_str();
+    Ok(sqlx::query!(
+        "UPDATE clips SET password = ? WHERE shortcode = ?",
+        password,
+        shortcode,
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?)
+}
+
+/// Uses up one view of a clip created with a view limit, deleting it after the last one.
+///
+/// Returns the number of views left, or `RowNotFound` when none were left to use.
+pub async fn consume_view(shortcode: &Shortcode, pool: &DatabasePool) -> Result<i64> {
+    let shortcode = shortcode.as_str();
+    let mut transaction = pool.begin().await?;
+    let updated = sqlx::query!(
+        "UPDATE clips SET max_views = max_views - 1 WHERE shortcode = ? AND max_views > 0",
+        shortcode
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return Err(sqlx::Error::RowNotFound.into());
+    }
+    let views_left = sqlx::query!("SELECT max_views FROM clips WHERE shortcode = ?", shortcode)
+        .fetch_one(&mut transaction)
+        .await?
+        .max_views
+        .unwrap_or_default();
+    if views_left <= 0 {
+        sqlx::query!("DELETE FROM clips WHERE shortcode = ?", shortcode)
+            .execute(&mut transaction)
+            .await?;
+    }
+    transaction.commit().await?;
+    Ok(views_left)
+}
+
+pub async fn increase_hit_count(
+    shortcode: &Shortcode,
+    hits: u32,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    let shortcode = shortcode.as_str();
+    //wrapped in OK to utilize ? operator
+    Ok(sqlx::query!(
+        "UPDATE clips SET hits = hits + ? WHERE shortcode = ?",
+        hits,
+        shortcode,
+    )
+    .execute(transaction)
+    .await
+    .map(|_| ())?)
+}
+
+/// Adds `views` to the bucket of a clip, ignoring clips deleted in the meantime.
+pub async fn record_views(
+    shortcode: &Shortcode,
+    bucket: i64,
+    source: &ViewSource,
+    views: u32,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    let shortcode = shortcode.as_str();
+    let agent = source.agent.as_str();
+    Ok(sqlx::query!(
+        r#"
+            INSERT INTO clip_views (shortcode, bucket, referrer_host, agent, views)
+            SELECT ?, ?, ?, ?, ?
+            WHERE EXISTS (SELECT 1 FROM clips WHERE shortcode = ?)
+            ON CONFLICT (shortcode, bucket, referrer_host, agent)
+            DO UPDATE SET views = views + excluded.views
+        "#,
+        shortcode,
+        bucket,
+        source.referrer_host,
+        agent,
+        views,
+        shortcode
+    )
+    .execute(transaction)
+    .await
+    .map(|_| ())?)
+}
+
+/// Views of a clip since `since`, summed into buckets of `period` seconds.
+pub async fn view_buckets(
+    shortcode: &Shortcode,
+    period: i64,
+    since: i64,
+    pool: &DatabasePool,
+) -> Result<Vec<model::ViewBucket>> {
+    Ok(sqlx::query_as::<_, model::ViewBucket>(
+        r#"
+            SELECT (bucket / ?) * ? AS bucket, SUM(views) AS views
+            FROM clip_views
+            WHERE shortcode = ? AND bucket >= ?
+            GROUP BY 1 ORDER BY 1
+        "#,
+    )
+    .bind(period)
+    .bind(period)
+    .bind(shortcode.as_str())
+    .bind(since)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Views of a clip since `since` per referrer host, or per agent class when `by_agent`.
+pub async fn view_counts(
+    shortcode: &Shortcode,
+    since: i64,
+    by_agent: bool,
+    pool: &DatabasePool,
+) -> Result<Vec<model::ViewCount>> {
+    let column = if by_agent { "agent" } else { "referrer_host" };
+    Ok(sqlx::query_as::<_, model::ViewCount>(&format!(
+        r#"
+            SELECT {column} AS name, SUM(views) AS views
+            FROM clip_views
+            WHERE shortcode = ? AND bucket >= ?
+            GROUP BY {column} ORDER BY views DESC LIMIT 10
+        "#,
+        column = column
+    ))
+    .bind(shortcode.as_str())
+    .bind(since)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn save_api_key(model: model::NewApiKey, pool: &DatabasePool) -> Result<()> {
+    sqlx::query!(
+        r#"
+            INSERT INTO api_keys (prefix, digest, label, created, expires, scopes)
+            VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+        model.prefix,
+        model.digest,
+        model.label,
+        model.created,
+        model.expires,
+        model.scopes
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?;
+    Ok(())
+}
+
+pub async fn get_api_key(prefix: &str, pool: &DatabasePool) -> Result<model::ApiKey> {
+    Ok(sqlx::query_as!(
+        model::ApiKey,
+        r#"
+            SELECT prefix, digest, label, created, expires, last_used_at, scopes
+            FROM api_keys WHERE prefix = ?
+        "#,
+        prefix
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn touch_api_key(prefix: &str, pool: &DatabasePool) -> Result<()> {
+    Ok(sqlx::query!(
+        "UPDATE api_keys SET last_used_at = strftime('%s', 'now') WHERE prefix = ?",
+        prefix
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?)
+}
+
+pub async fn revoke_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<RevocationStatus> {
+    let prefix = api_key.prefix();
+    let digest = api_key.digest();
+    Ok(sqlx::query!(
+        "DELETE FROM api_keys WHERE prefix = ? AND digest = ?",
+        prefix,
+        digest
+    )
+    .execute(pool)
+    .await
+    .map(|res| match res.rows_affected() {
+        0 => RevocationStatus::NotFound,
+        _ => RevocationStatus::Revoked,
+    })?)
+}
+
+pub async fn list_api_keys(pool: &DatabasePool) -> Result<Vec<model::ApiKey>> {
+    Ok(sqlx::query_as!(
+        model::ApiKey,
+        r#"
+            SELECT prefix, digest, label, created, expires, last_used_at, scopes
+            FROM api_keys ORDER BY created
+        "#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn revoke_api_key_by_prefix(
+    prefix: &str,
+    pool: &DatabasePool,
+) -> Result<RevocationStatus> {
+    Ok(
+        sqlx::query!("DELETE FROM api_keys WHERE prefix = ?", prefix)
+            .execute(pool)
+            .await
+            .map(|res| match res.rows_affected() {
+                0 => RevocationStatus::NotFound,
+                _ => RevocationStatus::Revoked,
+            })?,
+    )
+}
+
+/// Hashes keys stored in plain text before digests were introduced and removes the
+/// raw copies. Returns the number of keys moved.
+pub async fn upgrade_legacy_api_keys(pool: &DatabasePool) -> Result<u64> {
+    let mut transaction = pool.begin().await?;
+    let legacy = sqlx::query!("SELECT api_key FROM legacy_api_keys")
+        .fetch_all(&mut transaction)
+        .await?;
+    let mut moved = 0;
+    for row in legacy {
+        let key = match row.api_key {
+            Some(key) => key,
+            None => continue,
+        };
+        let prefix = api_key::prefix(&key);
+        let digest = api_key::digest(&key);
+        sqlx::query!(
+            r#"
+                INSERT INTO api_keys (prefix, digest, label, created, expires, last_used_at, scopes)
+                SELECT ?, ?, label, created, expires, last_used_at, scopes
+                FROM legacy_api_keys WHERE api_key = ?
+            "#,
+            prefix,
+            digest,
+            key
+        )
+        .execute(&mut transaction)
+        .await?;
+        moved += 1;
+    }
+    sqlx::query!("DELETE FROM legacy_api_keys")
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(moved)
+}
+
+/// Turns free text into an FTS5 query: every word must match, the last one as a prefix.
+fn fts_query(raw: &str) -> String {
+    let mut terms = raw
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    if let Some(last) = terms.last_mut() {
+        last.push('*');
+    }
+    terms.join(" ")
+}
+
+/// Full-text search over clip titles and content, best matches first.
+///
+/// Password protected, view limited and expired clips are never returned.
+pub async fn search_clips(
+    query: &str,
+    limit: u32,
+    pool: &DatabasePool,
+) -> Result<Vec<model::SearchResult>> {
+    let query = fts_query(query);
+    if query.is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(sqlx::query_as::<_, model::SearchResult>(
+        r#"
+            SELECT
+                clips.shortcode AS shortcode,
+                clips.title AS title,
+                snippet(clips_fts, 2, ?, ?, '…', 24) AS snippet,
+                bm25(clips_fts) AS rank
+            FROM clips_fts
+            JOIN clips ON clips.shortcode = clips_fts.shortcode
+            WHERE clips_fts MATCH ?
+                AND clips.password IS NULL
+                AND clips.max_views IS NULL
+                AND (clips.expires IS NULL OR clips.expires > strftime('%s', 'now'))
+            ORDER BY rank
+            LIMIT ?
+        "#,
+    )
+    .bind(model::MATCH_START)
+    .bind(model::MATCH_END)
+    .bind(query)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn delete_clip(shortcode: &Shortcode, pool: &DatabasePool) -> Result<u64> {
+    let shortcode = shortcode.as_str();
+    Ok(
+        sqlx::query!("DELETE FROM clips WHERE shortcode = ?", shortcode)
+            .execute(pool)
+            .await?
+            .rows_affected(),
+    )
+}
+
+pub async fn delete_expired(pool: &DatabasePool) -> Result<u64> {
+    Ok(
+        sqlx::query!(r#"DELETE FROM clips WHERE strftime('%s', 'now') > expires"#)
+            .execute(pool)
+            .await?
+            .rows_affected(),
+    )
+}
@@ -0,0 +1,122 @@
+use super::{check_key, BlobStore, Result, StorageError, StoredBlob};
+use chrono::{DateTime, Utc};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Keeps every blob as a file in one directory.
+pub struct FileStore {
+    root: PathBuf,
+}
+
+impl FileStore {
+    /// Creates `root` when it does not exist yet.
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        check_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[rocket::async_trait]
+impl BlobStore for FileStore {
+    /// Writes to a temporary file first so readers never see a partial blob.
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let path = self.path(key)?;
+        let partial = self.root.join(format!(".{}.partial", key));
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                Err(StorageError::NotFound(key.to_owned()))
+            }
+            read => Ok(read?),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            removed => Ok(removed?),
+        }
+    }
+
+    /// Skips partial writes and anything else that is not a valid key.
+    async fn list(&self) -> Result<Vec<StoredBlob>> {
+        let mut entries = tokio::fs::read_dir(&self.root).await?;
+        let mut blobs = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            let key = match entry.file_name().into_string() {
+                Ok(key) if check_key(&key).is_ok() => key,
+                _ => continue,
+            };
+            let modified = entry.metadata().await?.modified()?;
+            blobs.push(StoredBlob {
+                key,
+                modified: DateTime::<Utc>::from(modified),
+            });
+        }
+        Ok(blobs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FileStore;
+    use crate::data::storage::{BlobStore, Storage, StorageError};
+    use crate::test::async_runtime;
+    use chrono::{Duration, Utc};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    #[test]
+    fn blobs_round_trip_and_unused_ones_are_collected() {
+        let rt = async_runtime();
+        let dir = std::env::temp_dir().join(format!("clipstash-blobs-{}", Storage::new_key()));
+        let store = Arc::new(FileStore::new(&dir).unwrap());
+        let storage = Storage {
+            store: store.clone(),
+            threshold: 0,
+        };
+
+        rt.block_on(async move {
+            store.put("kept", b"kept".to_vec()).await.unwrap();
+            store.put("orphan", b"orphan".to_vec()).await.unwrap();
+            assert_eq!(store.get("kept").await.unwrap(), b"kept");
+            assert!(matches!(
+                store.get("missing").await,
+                Err(StorageError::NotFound(_))
+            ));
+            assert!(matches!(
+                store.get("../etc/passwd").await,
+                Err(StorageError::InvalidKey(_))
+            ));
+
+            let referenced: HashSet<String> = ["kept".to_owned()].into_iter().collect();
+            let past = Utc::now() - Duration::hours(1);
+            assert_eq!(storage.collect_garbage(&referenced, past).await.unwrap(), 0);
+            let future = Utc::now() + Duration::seconds(1);
+            assert_eq!(
+                storage.collect_garbage(&referenced, future).await.unwrap(),
+                1
+            );
+            let keys: Vec<_> = store
+                .list()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|blob| blob.key)
+                .collect();
+            assert_eq!(keys, vec!["kept".to_owned()]);
+        });
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+//! Blob storage for content too large to keep in a database row.
+//!
+//! Clip content, revisions and attachments above the configured threshold are written
+//! to a [`BlobStore`] under a fresh key and the row only records that key. Blobs are
+//! never overwritten, so anything no row refers to can be collected as garbage.
+
+mod fs;
+mod s3;
+
+pub use fs::FileStore;
+pub use s3::{S3Config, S3Store};
+
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Content up to this many bytes stays in the database when no threshold is configured.
+pub const DEFAULT_THRESHOLD: usize = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("blob not found: {0}")]
+    NotFound(String),
+    #[error("invalid blob key: {0}")]
+    InvalidKey(String),
+    #[error("blob storage is not configured")]
+    NotConfigured,
+    #[error("invalid blob storage: {0}")]
+    Config(String),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("S3 error {status}: {message}")]
+    S3 { status: u16, message: String },
+}
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+/// A blob found in a store.
+#[derive(Debug, Clone)]
+pub struct StoredBlob {
+    pub key: String,
+    pub modified: DateTime<Utc>,
+}
+
+#[rocket::async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()>;
+    /// Fails with [`StorageError::NotFound`] for unknown keys.
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+    /// Deleting a missing blob is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+    async fn list(&self) -> Result<Vec<StoredBlob>>;
+}
+
+/// Keys are generated by [`Storage::new_key`], anything else is refused so a key can
+/// never point outside the store.
+fn check_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && key.len() <= 64
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(StorageError::InvalidKey(key.to_owned()))
+    }
+}
+
+#[derive(Clone)]
+pub struct Storage {
+    pub store: Arc<dyn BlobStore>,
+    /// Data larger than this many bytes goes to the store.
+    pub threshold: usize,
+}
+
+static STORAGE: RwLock<Option<Storage>> = RwLock::new(None);
+
+impl Storage {
+    /// Opens the store named by `url`, either `file:<directory>` or
+    /// `s3://<bucket>[/<prefix>]` with the rest of the S3 settings taken from
+    /// the environment, see [`S3Config::from_env`].
+    pub fn open(url: &str, threshold: usize) -> Result<Self> {
+        let store: Arc<dyn BlobStore> = if let Some(dir) = url.strip_prefix("file:") {
+            Arc::new(FileStore::new(dir)?)
+        } else if let Some(location) = url.strip_prefix("s3://") {
+            let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+            Arc::new(S3Store::new(S3Config::from_env(bucket, prefix)?)?)
+        } else {
+            return Err(StorageError::Config(format!(
+                "unknown store '{}', expected file:<directory> or s3://<bucket>",
+                url
+            )));
+        };
+        Ok(Self { store, threshold })
+    }
+
+    /// Sets the store used by the queries for the rest of the process.
+    pub fn configure(storage: Storage) {
+        *STORAGE.write() = Some(storage);
+    }
+
+    /// `None` when everything is kept in the database.
+    pub fn current() -> Option<Storage> {
+        STORAGE.read().clone()
+    }
+
+    pub fn new_key() -> String {
+        uuid::Uuid::new_v4().to_simple().to_string()
+    }
+
+    /// Deletes blobs modified before `cutoff` whose key is not `referenced`.
+    ///
+    /// Blobs are written before the row that refers to them, so `cutoff` should leave
+    /// enough time for any save in progress to finish.
+    pub async fn collect_garbage(
+        &self,
+        referenced: &HashSet<String>,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64> {
+        let mut deleted = 0;
+        for blob in self.store.list().await? {
+            if blob.modified < cutoff && !referenced.contains(&blob.key) {
+                self.store.delete(&blob.key).await?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+}
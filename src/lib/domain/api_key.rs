@@ -0,0 +1,162 @@
+//! Metadata stored alongside every API key.
+
+use crate::Time;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+/// Leading bytes of a key kept in plain text to look it up.
+pub const PREFIX_LEN: usize = 6;
+
+/// Hex encoded identifier of a key, safe to store and display.
+pub fn prefix(key: &[u8]) -> String {
+    key.iter()
+        .take(PREFIX_LEN)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// SHA-256 of the whole key, the only secret part that is persisted.
+pub fn digest(key: &[u8]) -> Vec<u8> {
+    Sha256::digest(key).to_vec()
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown API key scope '{0}', expected read, write or admin")]
+pub struct UnknownScope(String);
+
+/// What a key may be used for. `Admin` implies every other scope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = UnknownScope;
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim() {
+            "read" => Ok(Scope::Read),
+            "write" => Ok(Scope::Write),
+            "admin" => Ok(Scope::Admin),
+            other => Err(UnknownScope(other.to_owned())),
+        }
+    }
+}
+
+/// Comma separated set of scopes, e.g. `read,write`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Scopes(Vec<Scope>);
+
+impl Scopes {
+    pub fn new(scopes: Vec<Scope>) -> Self {
+        let mut unique = Vec::with_capacity(scopes.len());
+        for scope in scopes {
+            if !unique.contains(&scope) {
+                unique.push(scope);
+            }
+        }
+        Self(unique)
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.0.contains(&scope) || self.0.contains(&Scope::Admin)
+    }
+
+    /// Reads scopes back from storage, unknown entries grant nothing.
+    pub fn from_stored(raw: &str) -> Self {
+        Self::new(raw.split(',').filter_map(|s| s.parse().ok()).collect())
+    }
+}
+
+/// Keys created before scopes existed could read and write clips.
+impl Default for Scopes {
+    fn default() -> Self {
+        Self(vec![Scope::Read, Scope::Write])
+    }
+}
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scopes = self.0.iter().map(Scope::as_str).collect::<Vec<_>>();
+        f.write_str(&scopes.join(","))
+    }
+}
+
+impl FromStr for Scopes {
+    type Err = UnknownScope;
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(
+            raw.split(',')
+                .filter(|scope| !scope.trim().is_empty())
+                .map(Scope::from_str)
+                .collect::<Result<_, _>>()?,
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ApiKeyInfo {
+    pub prefix: String,
+    pub label: String,
+    pub created: Time,
+    pub expires: Option<Time>,
+    pub last_used_at: Option<Time>,
+    pub scopes: Scopes,
+}
+
+impl ApiKeyInfo {
+    pub fn is_expired(&self) -> bool {
+        self.expires
+            .as_ref()
+            .map(|expires| expires.timestamp() <= Time::now().timestamp())
+            .unwrap_or(false)
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.allows(scope)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn admin_implies_other_scopes() {
+        let read_only = Scopes::from_str("read").unwrap();
+        assert!(read_only.allows(Scope::Read));
+        assert!(!read_only.allows(Scope::Write));
+
+        let admin = Scopes::from_str("admin").unwrap();
+        assert!(admin.allows(Scope::Read));
+        assert!(admin.allows(Scope::Write));
+    }
+
+    #[test]
+    fn scopes_round_trip() {
+        let scopes = Scopes::from_str("write, read,write").unwrap();
+        assert_eq!(scopes.to_string(), "write,read");
+        assert!(Scopes::from_str("read,owner").is_err());
+        assert_eq!(Scopes::from_stored("read,owner").to_string(), "read");
+    }
+}
@@ -1,4 +1,5 @@
 use crate::domain::clip::ClipError;
+use crate::domain::limits;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +15,15 @@ impl Content {
         }
     }
 
+    /// Rejects content longer than `max_bytes`.
+    pub fn validate(&self, max_bytes: u64) -> Result<(), ClipError> {
+        if self.0.len() as u64 > max_bytes {
+            Err(ClipError::ContentTooLong(max_bytes))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn into_inner(self) -> String {
         self.0
     }
@@ -26,6 +36,24 @@ impl Content {
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for Content {
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
-        Ok(Self::new(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?)
+        let content =
+            Self::new(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?;
+        content
+            .validate(limits::current().content_bytes())
+            .map_err(|e| form::Error::validation(format!("{}", e)))?;
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Content;
+
+    #[test]
+    fn long_content_is_rejected() {
+        let content = Content::new("a clip").unwrap();
+        assert!(content.validate(6).is_ok());
+        assert!(content.validate(5).is_err());
+        assert!(Content::new(" \n").is_err());
     }
 }
@@ -0,0 +1,49 @@
+use super::super::ClipError;
+use super::secret;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Secret handed to the creator of a clip, required to modify it afterwards.
+///
+/// Only an Argon2 hash of the token is persisted.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct EditToken(String);
+
+impl EditToken {
+    pub fn new() -> Self {
+        use rand::distributions::{Alphanumeric, DistString};
+        Self(Alphanumeric.sample_string(&mut rand::thread_rng(), 24))
+    }
+
+    pub fn hash(&self) -> Result<String, ClipError> {
+        secret::hash(self.0.as_str())
+    }
+
+    /// Checks this token against the stored hash of the clip owner's token.
+    pub fn verify(&self, stored: Option<&str>) -> bool {
+        stored
+            .and_then(|stored| secret::verify(self.0.as_str(), stored))
+            .unwrap_or(false)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl Default for EditToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for EditToken {
+    type Err = ClipError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_owned()))
+    }
+}
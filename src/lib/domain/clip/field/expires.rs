@@ -1,10 +1,12 @@
 use super::super::ClipError;
-use crate::domain::time::Time;
+use crate::domain::limits;
+use crate::domain::time::{self, Time};
+use chrono::{Duration, Utc};
 use rocket::form::{self, FromFormField, ValueField};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Expires(Option<Time>);
 
 impl Expires {
@@ -15,6 +17,39 @@ impl Expires {
     pub fn into_inner(self) -> Option<Time> {
         self.0
     }
+
+    /// Rejects expiration dates in the past or further away than `max_lifetime`.
+    pub fn validate(&self, max_lifetime: Option<Duration>) -> Result<(), ClipError> {
+        let expires = match &self.0 {
+            Some(expires) => expires.clone().into_inner(),
+            None if max_lifetime.is_some() => {
+                return Err(ClipError::InvalidDate(
+                    "an expiration date is required".to_owned(),
+                ))
+            }
+            None => return Ok(()),
+        };
+        let now = Utc::now();
+        if expires <= now {
+            return Err(ClipError::InvalidDate(
+                "expiration date is in the past".to_owned(),
+            ));
+        }
+        match max_lifetime {
+            Some(max_lifetime) if expires - now > max_lifetime => Err(ClipError::InvalidDate(
+                format!("clips must expire within {}", describe(max_lifetime)),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn describe(duration: Duration) -> String {
+    match (duration.num_days(), duration.num_hours()) {
+        (days, _) if days > 0 => format!("{} days", days),
+        (_, hours) if hours > 0 => format!("{} hours", hours),
+        _ => format!("{} minutes", duration.num_minutes()),
+    }
 }
 
 impl Default for Expires {
@@ -28,6 +63,11 @@ impl FromStr for Expires {
     fn from_str(raw: &str) -> Result<Self, Self::Err> {
         if raw.is_empty() {
             Ok(Self(None))
+        } else if let Some(duration) = time::parse_duration(raw) {
+            match Time::from_now(duration) {
+                Some(time) => Ok(Self::new(time)),
+                None => Err(ClipError::InvalidDate(raw.to_owned())),
+            }
         } else {
             match Time::from_str(raw) {
                 Ok(time) => Ok(Self::new(time)),
@@ -37,14 +77,56 @@ impl FromStr for Expires {
     }
 }
 
+impl<'de> Deserialize<'de> for Expires {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(raw) => Self::from_str(raw.trim()).map_err(serde::de::Error::custom),
+            None => Ok(Self(None)),
+        }
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for Expires {
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
-        if field.value.trim().is_empty() {
-            Ok(Self(None))
+        let expires = if field.value.trim().is_empty() {
+            Self(None)
         } else {
-            Ok(Self::from_str(field.value)
-                .map_err(|e| form::Error::validation(format!("{}", e)))?)
-        }
+            Self::from_str(field.value.trim())
+                .map_err(|e| form::Error::validation(format!("{}", e)))?
+        };
+        expires
+            .validate(limits::current().max_lifetime)
+            .map_err(|e| form::Error::validation(format!("{}", e)))?;
+        Ok(expires)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Expires;
+    use chrono::Duration;
+    use std::str::FromStr;
+
+    #[test]
+    fn relative_expiration_is_validated() {
+        let expires = Expires::from_str("1h").unwrap();
+        assert!(expires.validate(None).is_ok());
+        assert!(expires.validate(Some(Duration::days(1))).is_ok());
+        assert!(expires.validate(Some(Duration::minutes(10))).is_err());
+    }
+
+    #[test]
+    fn past_expiration_is_rejected() {
+        let expires = Expires::from_str("2020-01-01").unwrap();
+        assert!(expires.validate(None).is_err());
+    }
+
+    #[test]
+    fn json_accepts_relative_and_null() {
+        let expires: Expires = serde_json::from_str(r#""7d""#).unwrap();
+        assert!(expires.into_inner().is_some());
+        let expires: Expires = serde_json::from_str("null").unwrap();
+        assert!(expires.into_inner().is_none());
     }
 }
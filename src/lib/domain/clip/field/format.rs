@@ -0,0 +1,57 @@
+use super::super::ClipError;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How the clip page presents the content. The raw route always serves it untouched.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Text as written, without highlighting.
+    Plain,
+    /// Rendered to sanitized HTML.
+    Markdown,
+    /// Highlighted in the clip language.
+    #[default]
+    Code,
+}
+
+impl Format {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Plain => "plain",
+            Format::Markdown => "markdown",
+            Format::Code => "code",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Format {
+    type Err = ClipError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "plain" => Ok(Format::Plain),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "code" | "" => Ok(Format::Code),
+            other => Err(ClipError::InvalidFormat(other.to_owned())),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Format {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::from_str(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Format::Code)
+    }
+}
@@ -8,4 +8,4 @@ impl Hits {
     pub fn into_inner(self) -> u64 {
         self.0
     }
-}
\ No newline at end of file
+}
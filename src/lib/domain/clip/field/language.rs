@@ -0,0 +1,151 @@
+use super::super::ClipError;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::str::FromStr;
+
+/// Longest language name accepted.
+const MAX_LEN: usize = 32;
+
+/// Language of the clip content used for highlighting, `None` for plain text.
+///
+/// Names are lowercase syntax names or file extensions, e.g. `rust`, `py` or `c++`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Language(Option<String>);
+
+impl Language {
+    pub fn new<T: Into<Option<String>>>(language: T) -> Result<Self, ClipError> {
+        let language = match language.into() {
+            Some(language) => language.trim().to_ascii_lowercase(),
+            None => return Ok(Self(None)),
+        };
+        if language.is_empty() || language == "auto" {
+            return Ok(Self(None));
+        }
+        let valid = language.len() <= MAX_LEN
+            && language
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+#-_.".contains(c));
+        if valid {
+            Ok(Self(Some(language)))
+        } else {
+            Err(ClipError::InvalidLanguage(language))
+        }
+    }
+
+    /// Guesses the language of `content` from a few telltale lines, `None` when unsure.
+    pub fn detect(content: &str) -> Self {
+        let trimmed = content.trim_start();
+        let first_line = trimmed.lines().next().unwrap_or_default();
+        let has_line = |prefixes: &[&str]| {
+            content
+                .lines()
+                .map(str::trim_start)
+                .any(|line| prefixes.iter().any(|prefix| line.starts_with(prefix)))
+        };
+
+        let language = if let Some(interpreter) = first_line.strip_prefix("#!") {
+            [
+                ("python", "python"),
+                ("bash", "bash"),
+                ("sh", "bash"),
+                ("node", "javascript"),
+            ]
+            .iter()
+            .find(|(name, _)| interpreter.contains(name))
+            .map(|(_, language)| *language)
+        } else if (trimmed.starts_with('{') || trimmed.starts_with('['))
+            && serde_json::from_str::<serde_json::Value>(trimmed).is_ok()
+        {
+            Some("json")
+        } else if trimmed.starts_with("<?php") {
+            Some("php")
+        } else if trimmed.starts_with("<?xml") {
+            Some("xml")
+        } else if first_line
+            .to_ascii_lowercase()
+            .starts_with("<!doctype html")
+            || trimmed.starts_with("<html")
+        {
+            Some("html")
+        } else if has_line(&["fn ", "pub fn ", "impl ", "use std::", "#[derive"]) {
+            Some("rust")
+        } else if has_line(&["package main", "func main()"]) {
+            Some("go")
+        } else if has_line(&["#include <", "#include \""]) {
+            Some("c++")
+        } else if has_line(&["def ", "import ", "from "]) && content.contains(':') {
+            Some("python")
+        } else if has_line(&["public class ", "public static void main"]) {
+            Some("java")
+        } else if has_line(&["function ", "const ", "let ", "export ", "console.log("]) {
+            Some("javascript")
+        } else if has_line(&["SELECT ", "INSERT INTO ", "CREATE TABLE ", "UPDATE "]) {
+            Some("sql")
+        } else {
+            None
+        };
+        Self(language.map(str::to_owned))
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0
+    }
+}
+
+impl FromStr for Language {
+    type Err = ClipError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s.to_owned())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Language {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::new(field.value.to_owned())
+            .map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Language;
+
+    #[test]
+    fn names_are_normalized() {
+        assert_eq!(
+            Language::new(" Rust ".to_owned()).unwrap().as_deref(),
+            Some("rust")
+        );
+        assert!(!Language::new("auto".to_owned()).unwrap().is_set());
+        assert!(Language::new("<script>".to_owned()).is_err());
+    }
+
+    #[test]
+    fn detects_common_languages() {
+        let detect = |content: &str| Language::detect(content).into_inner();
+        assert_eq!(
+            detect("fn main() {\n    println!(\"hi\");\n}"),
+            Some("rust".to_owned())
+        );
+        assert_eq!(
+            detect("#!/usr/bin/env python3\nprint(1)"),
+            Some("python".to_owned())
+        );
+        assert_eq!(detect(r#"{"a": [1, 2]}"#), Some("json".to_owned()));
+        assert_eq!(detect("just some notes"), None);
+    }
+}
@@ -0,0 +1,49 @@
+use super::super::ClipError;
+use derive_more::Constructor;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Number of reads left before the clip deletes itself, `None` for unlimited.
+#[derive(Constructor, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct MaxViews(Option<u32>);
+
+impl MaxViews {
+    pub fn into_inner(self) -> Option<u32> {
+        self.0
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Rejects a limit of no views at all, which parsing catches but a deserialized
+    /// value skips.
+    pub fn validate(&self) -> Result<(), ClipError> {
+        match self.0 {
+            Some(0) => Err(ClipError::InvalidMaxViews("0".to_owned())),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for MaxViews {
+    type Err = ClipError;
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return Ok(Self(None));
+        }
+        match raw.parse::<u32>() {
+            Ok(views) if views > 0 => Ok(Self(Some(views))),
+            _ => Err(ClipError::InvalidMaxViews(raw.to_owned())),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for MaxViews {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::from_str(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+}
@@ -2,11 +2,17 @@ mod clip_id;
 pub use clip_id::ClipId;
 
 mod shortcode;
-pub use shortcode::Shortcode;
+pub use shortcode::{Shortcode, ShortcodeStyle};
 
 mod content;
 pub use content::Content;
 
+mod format;
+pub use format::Format;
+
+mod language;
+pub use language::Language;
+
 mod title;
 pub use title::Title;
 
@@ -20,4 +26,15 @@ mod password;
 pub use password::Password;
 
 mod hits;
-pub use hits::Hits;
\ No newline at end of file
+pub use hits::Hits;
+
+mod max_views;
+pub use max_views::MaxViews;
+
+mod upload;
+pub use upload::Upload;
+
+mod edit_token;
+pub use edit_token::EditToken;
+
+mod secret;
@@ -1,8 +1,10 @@
 use super::super::ClipError;
+use super::secret;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::str::FromStr;
-use uuid::Version;
+use subtle::ConstantTimeEq;
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
 pub struct Password(Option<String>);
@@ -29,6 +31,43 @@ impl Password {
     pub fn has_password(&self) -> bool {
         self.0.is_some()
     }
+
+    /// Returns an Argon2 PHC string of this password with a random salt.
+    pub fn hash(&self) -> Result<Self, ClipError> {
+        match &self.0 {
+            Some(password) => Ok(Self(Some(secret::hash(password)?))),
+            None => Ok(Self(None)),
+        }
+    }
+
+    /// Whether the stored value is a PHC hash rather than a legacy plaintext password.
+    pub fn is_hashed(&self) -> bool {
+        self.0.as_deref().map(secret::is_hash).unwrap_or(false)
+    }
+
+    /// Short digest of the stored value, or `None` without a password.
+    ///
+    /// Hashes are salted, so setting a password again changes the fingerprint even when
+    /// the password itself is the same.
+    pub fn fingerprint(&self) -> Option<String> {
+        self.0
+            .as_ref()
+            .map(|stored| hex::encode(&Sha256::digest(stored.as_bytes())[..16]))
+    }
+
+    /// Checks a user supplied `candidate` against this stored password.
+    ///
+    /// Rows written before hashing was introduced still hold plaintext, those are
+    /// compared in constant time so they can be upgraded on first successful unlock.
+    pub fn verify(&self, candidate: &Password) -> bool {
+        let (stored, candidate) = match (&self.0, &candidate.0) {
+            (Some(stored), Some(candidate)) => (stored, candidate),
+            (None, _) => return true,
+            (Some(_), None) => return false,
+        };
+        secret::verify(candidate, stored)
+            .unwrap_or_else(|| stored.as_bytes().ct_eq(candidate.as_bytes()).into())
+    }
 }
 
 impl Default for Password {
@@ -51,3 +90,27 @@ impl<'r> FromFormField<'r> for Password {
             .map_err(|e| form::Error::validation(format!("{}", e)))?)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Password;
+
+    #[test]
+    fn hashed_password_verifies() {
+        let password = Password::new("hunter2".to_owned()).unwrap();
+        let stored = password.hash().unwrap();
+        assert!(stored.is_hashed());
+        assert_ne!(stored, password);
+        assert!(stored.verify(&password));
+        assert!(!stored.verify(&Password::new("hunter3".to_owned()).unwrap()));
+        assert!(!stored.verify(&Password::default()));
+    }
+
+    #[test]
+    fn legacy_plaintext_password_verifies() {
+        let stored = Password::new("hunter2".to_owned()).unwrap();
+        assert!(!stored.is_hashed());
+        assert!(stored.verify(&Password::new("hunter2".to_owned()).unwrap()));
+        assert!(!stored.verify(&Password::new("hunter".to_owned()).unwrap()));
+    }
+}
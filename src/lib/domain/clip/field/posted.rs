@@ -1,7 +1,6 @@
-use serde::{Deserialize, Serialize};
 use crate::domain::time::Time;
 use derive_more::Constructor;
-
+use serde::{Deserialize, Serialize};
 
 #[derive(Constructor, Clone, Debug, Deserialize, Serialize)]
 pub struct Posted(Time);
@@ -10,4 +9,4 @@ impl Posted {
     fn into_inner(self) -> Time {
         self.0
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,29 @@
+//! Argon2 helpers shared by the fields that are stored as salted hashes.
+
+use crate::domain::clip::ClipError;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+/// Returns an Argon2 PHC string of `raw` with a random salt.
+pub(super) fn hash(raw: &str) -> Result<String, ClipError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(raw.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| ClipError::PasswordHash(e.to_string()))
+}
+
+pub(super) fn is_hash(stored: &str) -> bool {
+    PasswordHash::new(stored).is_ok()
+}
+
+/// Checks `raw` against a PHC string, `None` when `stored` is not a hash at all.
+pub(super) fn verify(raw: &str, stored: &str) -> Option<bool> {
+    let hash = PasswordHash::new(stored).ok()?;
+    Some(
+        Argon2::default()
+            .verify_password(raw.as_bytes(), &hash)
+            .is_ok(),
+    )
+}
@@ -1,30 +1,190 @@
 use super::super::ClipError;
 use derive_more::From;
+use parking_lot::RwLock;
 use rocket::request::FromParam;
 use rocket::{UriDisplayPath, UriDisplayQuery};
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::str::FromStr;
 
+/// Longest shortcode accepted from a request.
+pub const MAX_LEN: usize = 64;
+
+/// Words that may not be requested as shortcodes because routes or tooling use them.
+const RESERVED: [&str; 12] = [
+    "api",
+    "clip",
+    "delete",
+    "edit",
+    "key",
+    "new",
+    "raw",
+    "revisions",
+    "search",
+    "static",
+    "views",
+    "admin",
+];
+
+const LEGACY_ALPHABET: &str = "abcd1234";
+const BASE62_ALPHABET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const WORDS: &str = include_str!("words.txt");
+
+static STYLE: RwLock<Option<ShortcodeStyle>> = RwLock::new(None);
+
 #[derive(
     Debug, Clone, Deserialize, Serialize, From, UriDisplayPath, UriDisplayQuery, Eq, Hash, PartialEq,
 )]
 pub struct Shortcode(String);
 
-impl Shortcode {
-    pub fn new() -> Self {
+/// How new shortcodes are generated.
+///
+/// Parsed from `base62`, `base62:12`, `words`, `words:4` or `chars:<alphabet>:<length>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShortcodeStyle {
+    /// `length` characters drawn from `alphabet`.
+    Chars { alphabet: Vec<char>, length: usize },
+    /// `count` words joined by dashes, e.g. `correct-horse-battery`.
+    Words { count: usize },
+}
+
+impl ShortcodeStyle {
+    /// Sets the style used by `Shortcode::new` for the rest of the process.
+    pub fn configure(style: ShortcodeStyle) {
+        *STYLE.write() = Some(style);
+    }
+
+    pub fn current() -> Self {
+        STYLE.read().clone().unwrap_or_default()
+    }
+
+    pub fn generate(&self) -> Shortcode {
         use rand::prelude::*;
-        let allowed_chars = ['a', 'b', 'c', 'd', '1', '2', '3', '4'];
         let mut rng = thread_rng();
-        let mut shortcode = String::with_capacity(10);
-        for _ in 0..10 {
-            shortcode.push(
-                *allowed_chars
-                    .choose(&mut rng)
-                    .expect("sampling array should have values"),
-            );
+        let shortcode = match self {
+            ShortcodeStyle::Chars { alphabet, length } => (0..*length)
+                .map(|_| {
+                    *alphabet
+                        .choose(&mut rng)
+                        .expect("sampling array should have values")
+                })
+                .collect(),
+            ShortcodeStyle::Words { count } => (0..*count)
+                .map(|_| {
+                    WORDS
+                        .split_whitespace()
+                        .choose(&mut rng)
+                        .expect("word list should have values")
+                })
+                .collect::<Vec<_>>()
+                .join("-"),
+        };
+        Shortcode(shortcode)
+    }
+
+    fn chars(alphabet: &str, length: &str) -> Result<Self, ClipError> {
+        let mut chars: Vec<char> = alphabet.chars().collect();
+        chars.sort_unstable();
+        chars.dedup();
+        if chars.len() < 2 || !chars.iter().all(|c| Shortcode::is_valid_char(*c)) {
+            return Err(ClipError::InvalidShortcode(format!(
+                "alphabet '{}' needs at least two letters, digits, '-' or '_'",
+                alphabet
+            )));
+        }
+        Ok(ShortcodeStyle::Chars {
+            alphabet: chars,
+            length: parse_count(length, MAX_LEN)?,
+        })
+    }
+}
+
+fn parse_count(raw: &str, max: usize) -> Result<usize, ClipError> {
+    match raw.parse::<usize>() {
+        Ok(count) if count > 0 && count <= max => Ok(count),
+        _ => Err(ClipError::InvalidShortcode(format!(
+            "length '{}' should be between 1 and {}",
+            raw, max
+        ))),
+    }
+}
+
+impl Default for ShortcodeStyle {
+    fn default() -> Self {
+        ShortcodeStyle::Chars {
+            alphabet: LEGACY_ALPHABET.chars().collect(),
+            length: 10,
+        }
+    }
+}
+
+impl FromStr for ShortcodeStyle {
+    type Err = ClipError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("base62"), length, None) => Self::chars(BASE62_ALPHABET, length.unwrap_or("8")),
+            (Some("words"), count, None) => Ok(ShortcodeStyle::Words {
+                count: parse_count(count.unwrap_or("3"), 8)?,
+            }),
+            (Some("chars"), Some(alphabet), Some(length)) => Self::chars(alphabet, length),
+            _ => Err(ClipError::InvalidShortcode(format!(
+                "unknown style '{}', expected base62[:length], words[:count] or chars:<alphabet>:<length>",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ShortcodeStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShortcodeStyle::Chars { alphabet, length } => {
+                let alphabet: String = alphabet.iter().collect();
+                write!(f, "chars:{}:{}", alphabet, length)
+            }
+            ShortcodeStyle::Words { count } => write!(f, "words:{}", count),
         }
-        Self(shortcode)
+    }
+}
+
+impl Shortcode {
+    /// Generates a shortcode in the configured `ShortcodeStyle`.
+    pub fn new() -> Self {
+        ShortcodeStyle::current().generate()
+    }
+
+    /// Whether `shortcode` could name a clip. Checked against every style, not only
+    /// the configured one, so clips keep working after the style changes.
+    pub fn is_valid(shortcode: &str) -> bool {
+        !shortcode.is_empty()
+            && shortcode.len() <= MAX_LEN
+            && shortcode.chars().all(Self::is_valid_char)
+    }
+
+    /// Checks a shortcode requested by the clip creator, which also may not be reserved.
+    pub fn check_requested(&self) -> Result<(), ClipError> {
+        if !Self::is_valid(self.as_str()) {
+            return Err(ClipError::InvalidShortcode(format!(
+                "'{}' should be 1 to {} letters, digits, '-' or '_'",
+                self.0, MAX_LEN
+            )));
+        }
+        if RESERVED
+            .iter()
+            .any(|word| word.eq_ignore_ascii_case(self.as_str()))
+        {
+            return Err(ClipError::InvalidShortcode(format!(
+                "'{}' is reserved",
+                self.0
+            )));
+        }
+        Ok(())
+    }
+
+    fn is_valid_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '-' || c == '_'
     }
 
     pub fn as_str(&self) -> &str {
@@ -57,7 +217,11 @@ impl From<&str> for Shortcode {
 impl FromStr for Shortcode {
     type Err = ClipError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(s.into()))
+        if Self::is_valid(s) {
+            Ok(Self(s.into()))
+        } else {
+            Err(ClipError::InvalidShortcode(s.to_owned()))
+        }
     }
 }
 
@@ -65,6 +229,59 @@ impl<'r> FromParam<'r> for Shortcode {
     type Error = &'r str;
 
     fn from_param(param: &'r str) -> Result<Self, Self::Error> {
-        Ok(Shortcode::from(param))
+        if Self::is_valid(param) {
+            Ok(Shortcode::from(param))
+        } else {
+            Err(param)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn styles_generate_valid_shortcodes() {
+        let base62 = ShortcodeStyle::from_str("base62:12").unwrap();
+        let shortcode = base62.generate();
+        assert_eq!(shortcode.as_str().len(), 12);
+        assert!(Shortcode::is_valid(shortcode.as_str()));
+
+        let words = ShortcodeStyle::from_str("words").unwrap();
+        let shortcode = words.generate();
+        assert_eq!(shortcode.as_str().split('-').count(), 3);
+        assert!(Shortcode::is_valid(shortcode.as_str()));
+
+        let chars = ShortcodeStyle::from_str("chars:xy:4").unwrap();
+        assert!(chars
+            .generate()
+            .as_str()
+            .chars()
+            .all(|c| c == 'x' || c == 'y'));
+        assert_eq!(ShortcodeStyle::from_str(&chars.to_string()).unwrap(), chars);
+    }
+
+    #[test]
+    fn bad_styles_are_rejected() {
+        assert!(ShortcodeStyle::from_str("base62:0").is_err());
+        assert!(ShortcodeStyle::from_str("chars:a:8").is_err());
+        assert!(ShortcodeStyle::from_str("chars:a/b:8").is_err());
+        assert!(ShortcodeStyle::from_str("emoji").is_err());
+    }
+
+    #[test]
+    fn garbage_params_are_rejected() {
+        assert!(Shortcode::from_param("correct-horse_1").is_ok());
+        assert!(Shortcode::from_param("..%2f").is_err());
+        assert!(Shortcode::from_param("clip.txt").is_err());
+        assert!(Shortcode::from_param(&"a".repeat(MAX_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn requested_shortcodes_skip_reserved_words() {
+        assert!(Shortcode::from("oncall-runbook").check_requested().is_ok());
+        assert!(Shortcode::from("Raw").check_requested().is_err());
+        assert!(Shortcode::from("on call").check_requested().is_err());
     }
 }
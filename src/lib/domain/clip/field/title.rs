@@ -1,4 +1,5 @@
 use super::super::ClipError;
+use crate::domain::limits;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
@@ -22,6 +23,16 @@ impl Title {
         }
     }
 
+    /// Rejects titles longer than `max_chars`.
+    pub fn validate(&self, max_chars: usize) -> Result<(), ClipError> {
+        match &self.0 {
+            Some(title) if title.chars().count() > max_chars => {
+                Err(ClipError::TitleTooLong(max_chars))
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn into_inner(self) -> Option<String> {
         self.0
     }
@@ -43,6 +54,10 @@ impl FromStr for Title {
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for Title {
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
-        Ok(Self::new(field.value.to_owned()))
+        let title = Self::new(field.value.to_owned());
+        title
+            .validate(limits::current().title_chars())
+            .map_err(|e| form::Error::validation(format!("{}", e)))?;
+        Ok(title)
     }
 }
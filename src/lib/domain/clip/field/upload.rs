@@ -0,0 +1,157 @@
+use crate::domain::clip::{Attachment, AttachmentData, ClipError};
+use crate::domain::limits;
+use rocket::data::ToByteUnit;
+use rocket::form::{self, DataField, FromFormField, ValueField};
+
+/// Longest file name kept, in characters.
+const MAX_NAME_LEN: usize = 255;
+
+/// File uploaded with a new clip, `None` when the form had no file.
+#[derive(Clone, Debug, Default)]
+pub struct Upload(Option<AttachmentData>);
+
+impl Upload {
+    /// Checks an uploaded file against the attachment limit and works out its type.
+    ///
+    /// The type is sniffed from the data. The `declared` type sent by the client is
+    /// only used for data that could not be recognised, and never to claim an image.
+    pub fn new(
+        file_name: Option<&str>,
+        declared: Option<&str>,
+        data: Vec<u8>,
+    ) -> Result<Self, ClipError> {
+        let file_name = file_name.unwrap_or_default();
+        if data.is_empty() {
+            return if file_name.is_empty() {
+                Ok(Self(None))
+            } else {
+                Err(ClipError::InvalidAttachment(format!(
+                    "'{}' is empty",
+                    file_name
+                )))
+            };
+        }
+        let max = limits::current().attachment_bytes();
+        if data.len() as u64 > max {
+            return Err(ClipError::InvalidAttachment(format!(
+                "files may be at most {} bytes",
+                max
+            )));
+        }
+        let mime = match infer::get(&data) {
+            Some(kind) => kind.mime_type().to_owned(),
+            None => declared
+                .map(|mime| mime.trim().to_ascii_lowercase())
+                .filter(|mime| is_plain_mime(mime) && !mime.starts_with("image/"))
+                .unwrap_or_else(|| "application/octet-stream".to_owned()),
+        };
+        Ok(Self(Some(AttachmentData {
+            attachment: Attachment {
+                name: sanitize_name(file_name),
+                mime,
+                size: data.len() as u64,
+            },
+            data,
+        })))
+    }
+
+    pub fn into_inner(self) -> Option<AttachmentData> {
+        self.0
+    }
+}
+
+/// Whether `mime` is a bare `type/subtype`, without parameters or odd characters.
+fn is_plain_mime(mime: &str) -> bool {
+    let mut parts = mime.split('/');
+    let valid = |part: Option<&str>| {
+        part.map(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        })
+        .unwrap_or(false)
+    };
+    valid(parts.next()) && valid(parts.next()) && parts.next().is_none()
+}
+
+/// Drops directories and control characters from an uploaded file name.
+fn sanitize_name(file_name: &str) -> String {
+    let name: String = file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_NAME_LEN)
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "attachment".to_owned(),
+        name => name.to_owned(),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Upload {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        if field.value.is_empty() {
+            Ok(Self(None))
+        } else {
+            Err(form::Error::validation("attachments must be uploaded as files").into())
+        }
+    }
+
+    async fn from_data(field: DataField<'r, '_>) -> form::Result<'r, Self> {
+        let max = limits::current().attachment_bytes();
+        let data = field
+            .data
+            .open(max.saturating_add(1).bytes())
+            .into_bytes()
+            .await
+            .map_err(|e| form::Error::validation(format!("upload failed: {}", e)))?;
+        let file_name = field
+            .file_name
+            .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str());
+        let declared = field.content_type.to_string();
+        Ok(Self::new(file_name, Some(&declared), data.into_inner())
+            .map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Upload;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn types_are_sniffed_not_trusted() {
+        let png = Upload::new(Some("../../shot.png"), Some("text/html"), PNG.to_vec())
+            .unwrap()
+            .into_inner()
+            .unwrap();
+        assert_eq!(png.attachment.mime, "image/png");
+        assert_eq!(png.attachment.name, "shot.png");
+        assert!(png.attachment.is_image());
+
+        let fake = Upload::new(Some("x.png"), Some("image/png"), b"not a png".to_vec())
+            .unwrap()
+            .into_inner()
+            .unwrap();
+        assert_eq!(fake.attachment.mime, "application/octet-stream");
+        assert!(!fake.attachment.is_image());
+    }
+
+    #[test]
+    fn empty_uploads() {
+        assert!(Upload::new(None, None, vec![])
+            .unwrap()
+            .into_inner()
+            .is_none());
+        assert!(Upload::new(Some("empty.txt"), None, vec![]).is_err());
+    }
+}
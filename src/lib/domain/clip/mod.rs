@@ -7,10 +7,28 @@ use thiserror::Error;
 pub enum ClipError {
     #[error("invalid password: {0}")]
     InvalidPassword(String),
+    #[error("password hashing error: {0}")]
+    PasswordHash(String),
+    #[error("invalid shortcode: {0}")]
+    InvalidShortcode(String),
+    #[error("invalid format: {0}, expected plain, markdown or code")]
+    InvalidFormat(String),
+    #[error("invalid language: {0}")]
+    InvalidLanguage(String),
     #[error("invalid title: {0}")]
     InvalidTitle(String),
+    #[error("invalid max views: {0}, expected a positive number")]
+    InvalidMaxViews(String),
+    #[error("invalid attachment: {0}")]
+    InvalidAttachment(String),
+    #[error("stored content could not be decompressed: {0}")]
+    Decompress(String),
     #[error("empty content not allowed")]
     EmptyContent,
+    #[error("content is too long, clips may be at most {0} bytes")]
+    ContentTooLong(u64),
+    #[error("title is too long, titles may be at most {0} characters")]
+    TitleTooLong(usize),
     #[error("invalid date: {0}")]
     InvalidDate(String),
     #[error("error while parsing date: {0}")]
@@ -30,6 +48,79 @@ pub struct Clip {
     pub title: field::Title,
     pub posted: field::Posted,
     pub expires: field::Expires,
+    #[serde(skip)]
     pub password: field::Password,
     pub hits: field::Hits,
+    #[serde(default)]
+    pub max_views: field::MaxViews,
+    /// Only present in the response to the request that created the clip.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit_token: Option<field::EditToken>,
+    /// Used to highlight the content, detected when the creator did not pick one.
+    #[serde(default)]
+    pub language: field::Language,
+    #[serde(default)]
+    pub format: field::Format,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+    /// The content as stored by the data layer when it was compressed with zstd, so it
+    /// can be sent to clients that accept zstd without compressing it again.
+    #[serde(skip)]
+    pub content_zstd: Option<Vec<u8>>,
+}
+
+/// Describes the file uploaded with a clip. The file itself is served from its own route.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Attachment {
+    /// File name as uploaded, without any directories.
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+}
+
+impl Attachment {
+    /// Raster images are previewed on the clip page and served inline, everything
+    /// else is offered as a download. SVG can carry script and is not included.
+    pub fn is_image(&self) -> bool {
+        matches!(
+            self.mime.as_str(),
+            "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "image/bmp" | "image/avif"
+        )
+    }
+}
+
+/// An attachment together with its bytes.
+#[derive(Debug, Clone)]
+pub struct AttachmentData {
+    pub attachment: Attachment,
+    pub data: Vec<u8>,
+}
+
+/// A previous version of a clip, saved whenever the clip is updated.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Revision {
+    pub shortcode: field::Shortcode,
+    pub revision: u32,
+    pub content: field::Content,
+    pub title: field::Title,
+    pub created: crate::Time,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchResult {
+    pub shortcode: field::Shortcode,
+    pub title: field::Title,
+    /// Plain text excerpt around the matches.
+    pub snippet: String,
+    /// Where the matched terms are in `snippet`.
+    pub matches: Vec<SnippetMatch>,
+    /// Lower is a better match.
+    pub rank: f64,
+}
+
+/// A term of a snippet that matched the query, as byte offsets into the snippet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SnippetMatch {
+    pub start: usize,
+    pub end: usize,
 }
@@ -0,0 +1,57 @@
+//! Server wide limits applied while validating clip fields.
+//!
+//! Field types are parsed by Rocket and serde without access to managed state, so
+//! the limits are configured once at startup and read from here.
+
+use chrono::Duration;
+use parking_lot::RwLock;
+
+/// Largest attachment accepted when no other limit is configured.
+pub const DEFAULT_MAX_ATTACHMENT_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Longest content accepted when no other limit is configured.
+pub const DEFAULT_MAX_CONTENT_BYTES: u64 = 1024 * 1024;
+
+/// Longest title accepted when no other limit is configured.
+pub const DEFAULT_MAX_TITLE_CHARS: usize = 200;
+
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// Furthest a clip may expire in the future, `None` for no maximum.
+    pub max_lifetime: Option<Duration>,
+    /// Most clips a single API key may have stored at once.
+    pub max_clips_per_key: Option<u64>,
+    /// Most bytes of content a single API key may have stored at once.
+    pub max_bytes_per_key: Option<u64>,
+    /// Largest file that may be attached to a clip, `None` for the default.
+    pub max_attachment_bytes: Option<u64>,
+    /// Longest clip content in bytes, `None` for the default.
+    pub max_content_bytes: Option<u64>,
+    /// Longest clip title in characters, `None` for the default.
+    pub max_title_chars: Option<usize>,
+}
+
+impl Limits {
+    pub fn attachment_bytes(&self) -> u64 {
+        self.max_attachment_bytes
+            .unwrap_or(DEFAULT_MAX_ATTACHMENT_BYTES)
+    }
+
+    pub fn content_bytes(&self) -> u64 {
+        self.max_content_bytes.unwrap_or(DEFAULT_MAX_CONTENT_BYTES)
+    }
+
+    pub fn title_chars(&self) -> usize {
+        self.max_title_chars.unwrap_or(DEFAULT_MAX_TITLE_CHARS)
+    }
+}
+
+static LIMITS: RwLock<Option<Limits>> = RwLock::new(None);
+
+pub fn configure(limits: Limits) {
+    *LIMITS.write() = Some(limits);
+}
+
+pub fn current() -> Limits {
+    LIMITS.read().clone().unwrap_or_default()
+}
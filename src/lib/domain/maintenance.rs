@@ -7,6 +7,7 @@ pub struct Maintenance;
 //TODO dig for more ticker implementations
 impl Maintenance {
     pub fn spawn(pool: DatabasePool, handle: Handle) -> Self {
+        let gc_pool = pool.clone();
         handle.spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(60));
             loop {
@@ -16,6 +17,15 @@ impl Maintenance {
                 }
             }
         });
+        handle.spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+            loop {
+                interval.tick().await;
+                if let Err(e) = service::action::collect_garbage(&gc_pool).await {
+                    eprintln!("failed to collect unused blobs: {}", e)
+                }
+            }
+        });
         Self
     }
 }
@@ -1,5 +1,9 @@
+pub mod api_key;
 pub mod clip;
+pub mod limits;
 pub mod maintenance;
 pub mod time;
+pub mod unlock;
+pub mod views;
 
 pub use clip::Clip;
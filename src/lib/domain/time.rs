@@ -1,13 +1,17 @@
-use std::str::FromStr;
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use derive_more::From;
 use serde::Deserialize;
 use serde::Serialize;
+use std::str::FromStr;
 
 #[derive(Clone, Debug, From, Deserialize, Serialize)]
 pub struct Time(DateTime<Utc>);
 
 impl Time {
+    pub fn now() -> Self {
+        Time(Utc::now())
+    }
+
     pub fn into_inner(self) -> DateTime<Utc> {
         self.0
     }
@@ -19,15 +23,71 @@ impl Time {
     pub fn from_naive_utc(datetime: NaiveDateTime) -> Self {
         Time(DateTime::from_utc(datetime, Utc))
     }
+
+    /// Time `duration` from now, `None` if it does not fit in a date.
+    pub fn from_now(duration: Duration) -> Option<Self> {
+        Utc::now().checked_add_signed(duration).map(Time)
+    }
 }
 
 impl FromStr for Time {
     type Err = chrono::ParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        //2022-05-22 date format
-        match format!("{}T00:00:00z", s).parse::<DateTime<Utc>>() {
-            Ok(time) => Ok(time.into()),
-            Err(e) => Err(e)
+        //2022-05-22T10:00:00+02:00 (RFC 3339) or 2022-05-22 date format
+        match DateTime::parse_from_rfc3339(s) {
+            Ok(time) => Ok(Time(time.with_timezone(&Utc))),
+            Err(_) => match format!("{}T00:00:00z", s).parse::<DateTime<Utc>>() {
+                Ok(time) => Ok(time.into()),
+                Err(e) => Err(e),
+            },
         }
     }
-}
\ No newline at end of file
+}
+
+/// Parses a relative duration such as `30s`, `10m`, `1h`, `7d` or `2w`.
+pub fn parse_duration(raw: &str) -> Option<Duration> {
+    let raw = raw.trim();
+    let unit_at = raw.len().checked_sub(1)?;
+    if !raw.is_char_boundary(unit_at) {
+        return None;
+    }
+    let (amount, unit) = raw.split_at(unit_at);
+    let amount = amount.parse::<i64>().ok().filter(|amount| *amount > 0)?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    // Duration::seconds panics past i64::MAX milliseconds
+    let secs = amount
+        .checked_mul(unit_secs)
+        .filter(|secs| *secs <= i64::MAX / 1000)?;
+    Some(Duration::seconds(secs))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_relative_durations() {
+        assert_eq!(parse_duration("10m"), Some(Duration::minutes(10)));
+        assert_eq!(parse_duration("1h"), Some(Duration::hours(1)));
+        assert_eq!(parse_duration("7d"), Some(Duration::days(7)));
+        assert_eq!(parse_duration("0d"), None);
+        assert_eq!(parse_duration("-1h"), None);
+        assert_eq!(parse_duration("2022-05-22"), None);
+        assert_eq!(parse_duration("h"), None);
+    }
+
+    #[test]
+    fn parses_dates_and_rfc3339() {
+        let date = Time::from_str("2022-05-22").unwrap();
+        assert_eq!(date.into_inner().to_rfc3339(), "2022-05-22T00:00:00+00:00");
+        let time = Time::from_str("2022-05-22T10:30:00+02:00").unwrap();
+        assert_eq!(time.into_inner().to_rfc3339(), "2022-05-22T08:30:00+00:00");
+    }
+}
@@ -0,0 +1,102 @@
+//! Proof that the right password was entered for a clip.
+
+use crate::Clip;
+use chrono::{Duration, Utc};
+
+/// Names the unlocked clip by id and carries a fingerprint of its password hash, so it
+/// stops working once the password changes or another clip takes over the shortcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unlock {
+    pub clip_id: String,
+    pub fingerprint: String,
+    /// Unix timestamp after which the unlock is no longer accepted.
+    pub expires: i64,
+}
+
+impl Unlock {
+    /// Unlocks `clip` with its current password for `lifetime`.
+    pub fn new(clip: &Clip, lifetime: Duration) -> Self {
+        Self {
+            clip_id: clip.clip_id.clone().into_inner().into(),
+            fingerprint: clip.password.fingerprint().unwrap_or_default(),
+            expires: (Utc::now() + lifetime).timestamp(),
+        }
+    }
+
+    /// Whether this unlock was made for `clip` with its current password and has not
+    /// expired.
+    pub fn is_valid_for(&self, clip: &Clip) -> bool {
+        let clip_id: String = clip.clip_id.clone().into_inner().into();
+        self.clip_id == clip_id
+            && Some(&self.fingerprint) == clip.password.fingerprint().as_ref()
+            && self.expires > Utc::now().timestamp()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Unlock;
+    use crate::data::Dbid;
+    use crate::domain::clip::field::{ClipId, Content, Expires, Hits, Password, Posted, Title};
+    use crate::domain::time::Time;
+    use crate::{Clip, Shortcode};
+    use chrono::Duration;
+
+    fn clip(password: &str) -> Clip {
+        let password = Password::new(password.to_owned()).unwrap();
+        Clip {
+            clip_id: ClipId::new(Dbid::new()),
+            shortcode: Shortcode::from("aaaa"),
+            content: Content::new("secret").unwrap(),
+            title: Title::default(),
+            posted: Posted::new(Time::now()),
+            expires: Expires::default(),
+            password: password.hash().unwrap(),
+            hits: Hits::new(0),
+            max_views: Default::default(),
+            edit_token: None,
+            language: Default::default(),
+            format: Default::default(),
+            attachment: None,
+            content_zstd: None,
+        }
+    }
+
+    fn unlock(clip: &Clip) -> Unlock {
+        Unlock::new(clip, Duration::hours(1))
+    }
+
+    #[test]
+    fn unlock_is_scoped_to_its_clip() {
+        let clip = clip("hunter2");
+        let unlock = unlock(&clip);
+        assert!(unlock.is_valid_for(&clip));
+        let mut other = clip.clone();
+        other.clip_id = ClipId::new(Dbid::new());
+        assert!(!unlock.is_valid_for(&other));
+    }
+
+    #[test]
+    fn unlock_is_rejected_after_password_change() {
+        let mut clip = clip("hunter2");
+        let unlock = unlock(&clip);
+        clip.password = Password::new("hunter3".to_owned()).unwrap().hash().unwrap();
+        assert!(!unlock.is_valid_for(&clip));
+    }
+
+    #[test]
+    fn unlock_does_not_open_a_new_clip_under_the_same_shortcode() {
+        let first = clip("hunter2");
+        let unlock = unlock(&first);
+        let second = clip("hunter2");
+        assert_eq!(first.shortcode, second.shortcode);
+        assert!(!unlock.is_valid_for(&second));
+    }
+
+    #[test]
+    fn expired_unlock_is_rejected() {
+        let clip = clip("hunter2");
+        let unlock = Unlock::new(&clip, Duration::seconds(-1));
+        assert!(!unlock.is_valid_for(&clip));
+    }
+}
@@ -0,0 +1,144 @@
+//! Clip views bucketed by time, referrer and kind of client.
+
+use crate::Time;
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// Coarse class of the client that viewed a clip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentClass {
+    Browser,
+    Cli,
+    Bot,
+    Other,
+}
+
+impl AgentClass {
+    pub fn classify(user_agent: Option<&str>) -> Self {
+        let agent = match user_agent {
+            Some(agent) => agent.to_ascii_lowercase(),
+            None => return AgentClass::Other,
+        };
+        const BOTS: [&str; 4] = ["bot", "spider", "crawl", "slurp"];
+        const CLIS: [&str; 6] = [
+            "curl/",
+            "wget/",
+            "httpie/",
+            "python-requests/",
+            "reqwest",
+            "go-http-client/",
+        ];
+        if BOTS.iter().any(|bot| agent.contains(bot)) {
+            AgentClass::Bot
+        } else if CLIS.iter().any(|cli| agent.starts_with(cli)) {
+            AgentClass::Cli
+        } else if agent.starts_with("mozilla/") {
+            AgentClass::Browser
+        } else {
+            AgentClass::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AgentClass::Browser => "browser",
+            AgentClass::Cli => "cli",
+            AgentClass::Bot => "bot",
+            AgentClass::Other => "other",
+        }
+    }
+}
+
+/// Where a single view came from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ViewSource {
+    /// Host of the `Referer`, empty when there was none.
+    pub referrer_host: String,
+    pub agent: AgentClass,
+}
+
+/// Granularity of a view history.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, rocket::FromFormField,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewPeriod {
+    Hour,
+    #[default]
+    Day,
+}
+
+impl ViewPeriod {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            ViewPeriod::Hour => 60 * 60,
+            ViewPeriod::Day => 24 * 60 * 60,
+        }
+    }
+
+    /// How far back a history of this granularity reaches.
+    pub fn window(&self) -> Duration {
+        match self {
+            ViewPeriod::Hour => Duration::hours(48),
+            ViewPeriod::Day => Duration::days(30),
+        }
+    }
+
+    /// Start of the bucket holding `timestamp`.
+    pub fn bucket(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.seconds())
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ViewBucket {
+    pub start: Time,
+    pub views: u64,
+}
+
+impl ViewBucket {
+    pub fn new(start: i64, views: u64) -> Self {
+        let start = NaiveDateTime::from_timestamp_opt(start, 0).unwrap_or_default();
+        Self {
+            start: Time::from_naive_utc(start),
+            views,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ViewCount {
+    pub name: String,
+    pub views: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ViewHistory {
+    pub period: ViewPeriod,
+    pub buckets: Vec<ViewBucket>,
+    pub referrers: Vec<ViewCount>,
+    pub agents: Vec<ViewCount>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classifies_user_agents() {
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/115.0";
+        assert_eq!(AgentClass::classify(Some(firefox)), AgentClass::Browser);
+        assert_eq!(AgentClass::classify(Some("curl/8.1.2")), AgentClass::Cli);
+        let google = "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+        assert_eq!(AgentClass::classify(Some(google)), AgentClass::Bot);
+        assert_eq!(AgentClass::classify(None), AgentClass::Other);
+    }
+
+    #[test]
+    fn buckets_start_on_the_hour_and_day() {
+        let timestamp = 1_700_000_123;
+        assert_eq!(ViewPeriod::Hour.bucket(timestamp) % 3600, 0);
+        assert_eq!(ViewPeriod::Day.bucket(timestamp), 1_699_920_000);
+    }
+}
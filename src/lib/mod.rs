@@ -13,6 +13,7 @@ pub use web::api::API_KEY_HEADER;
 
 use crate::domain::maintenance::Maintenance;
 use crate::web::counter::HitCounter;
+use crate::web::rate_limit::RateLimiter;
 use data::AppDatabase;
 use rocket::fs::FileServer;
 use rocket::{Build, Rocket};
@@ -23,14 +24,41 @@ pub struct RocketConfig {
     pub database: AppDatabase,
     pub hit_counter: HitCounter,
     pub maintenance: Maintenance,
+    pub rate_limiter: RateLimiter,
+}
+
+/// Room left in request bodies for the fields sent next to the content and attachment.
+const FORM_FIELDS_BYTES: u64 = 64 * 1024;
+
+/// Most bytes JSON takes to escape one byte of content, such as `\u001f` for a control
+/// character.
+const JSON_ESCAPE_BYTES: u64 = 6;
+
+/// Rocket configuration with body limits that fit the largest content the limits allow,
+/// so oversized clips are rejected with a clip error rather than by Rocket while reading
+/// them.
+fn figment() -> rocket::figment::Figment {
+    let limits = domain::limits::current();
+    let body = limits.content_bytes().saturating_add(FORM_FIELDS_BYTES);
+    let json = limits
+        .content_bytes()
+        .saturating_mul(JSON_ESCAPE_BYTES)
+        .saturating_add(FORM_FIELDS_BYTES);
+    let data_form = limits.attachment_bytes().saturating_add(body);
+    rocket::Config::figment()
+        .merge(("limits.json", json))
+        .merge(("limits.form", body))
+        .merge(("limits.data-form", data_form))
 }
 
 pub fn new_rocket(config: RocketConfig) -> Rocket<Build> {
-    rocket::build()
+    rocket::custom(figment())
         .manage::<AppDatabase>(config.database)
         .manage::<Renderer>(config.renderer)
         .manage::<HitCounter>(config.hit_counter)
+        .attach(HitCounter::flush_on_shutdown())
         .manage::<Maintenance>(config.maintenance)
+        .manage::<RateLimiter>(config.rate_limiter)
         .mount("/", web::http::routes())
         .mount("/api/clip", web::api::routes())
         .mount("/static", FileServer::from("static"))
@@ -1,29 +1,371 @@
-use crate::data::{query, DatabasePool, Transaction};
+use crate::data::storage::Storage;
+use crate::data::{model, query, DataError, DatabasePool, Transaction};
+use crate::domain::api_key::ApiKeyInfo;
+use crate::domain::clip::{field, AttachmentData, Revision, SearchResult};
+use crate::domain::limits;
+use crate::domain::unlock::Unlock;
+use crate::domain::views::{ViewHistory, ViewPeriod, ViewSource};
 use crate::service::ask;
 use crate::web::api::ApiKey;
 use crate::{Clip, ServiceError, Shortcode};
-use std::convert::TryInto;
+use chrono::Utc;
+use std::convert::{TryFrom, TryInto};
+use subtle::ConstantTimeEq;
 
 pub async fn get_clip(req: ask::GetClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
+    let clip = check_password(req, pool).await?;
+    consume_view(clip, pool).await
+}
+
+async fn check_password(req: ask::GetClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
     let user_password = req.password.clone();
     let clip: Clip = query::get_clip(req, pool).await?.try_into()?;
-    if clip.password.has_password() {
-        if clip.password == user_password {
-            Ok(clip)
-        } else {
-            Err(ServiceError::PermissionError("Invalid password".to_owned()))
-        }
-    } else {
+    verify_password(clip, &user_password, pool).await
+}
+
+/// Lets the caller in when `unlock` was issued for this very clip and its current
+/// password, anyone else has to send the password.
+async fn check_unlock(
+    req: ask::GetClip,
+    unlock: &Unlock,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    let user_password = req.password.clone();
+    let clip: Clip = query::get_clip(req, pool).await?.try_into()?;
+    if unlock.is_valid_for(&clip) {
         Ok(clip)
+    } else {
+        verify_password(clip, &user_password, pool).await
+    }
+}
+
+async fn verify_password(
+    mut clip: Clip,
+    user_password: &field::Password,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    if !clip.password.verify(user_password) {
+        return Err(ServiceError::PermissionError("Invalid password".to_owned()));
+    }
+    if clip.password.has_password() && !clip.password.is_hashed() {
+        let hashed = user_password.hash()?;
+        query::update_clip_password(&clip.shortcode, hashed.clone().into_inner(), pool).await?;
+        clip.password = hashed;
+    }
+    Ok(clip)
+}
+
+/// Fetches a clip whose password the caller proved earlier through `unlock`.
+pub async fn get_unlocked_clip(
+    req: ask::GetClip,
+    unlock: &Unlock,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    let clip = check_unlock(req, unlock, pool).await?;
+    consume_view(clip, pool).await
+}
+
+/// Fetches a clip for its page, with the image attached to it when the clip has a view
+/// limit.
+///
+/// Loading the image in a second request would use up another view, or find the clip
+/// gone once the page used the last one, so the page carries the image and reading both
+/// counts as a single view. Checked with `unlock` when the browser has one.
+pub async fn get_clip_page(
+    req: ask::GetClip,
+    unlock: Option<&Unlock>,
+    pool: &DatabasePool,
+) -> Result<(Clip, Option<AttachmentData>), ServiceError> {
+    let clip = match unlock {
+        Some(unlock) => check_unlock(req, unlock, pool).await?,
+        None => check_password(req, pool).await?,
+    };
+    let preview = match &clip.attachment {
+        Some(attachment) if attachment.is_image() && clip.max_views.is_limited() => Some(
+            query::get_attachment(&clip.shortcode, pool)
+                .await?
+                .try_into()?,
+        ),
+        _ => None,
+    };
+    let clip = consume_view(clip, pool).await?;
+    Ok((clip, preview))
+}
+
+/// Fetches a clip for its owner, skipping the password check and leaving any view
+/// limit untouched.
+pub async fn get_owned_clip(
+    shortcode: Shortcode,
+    edit_token: &field::EditToken,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    check_owner(&shortcode, edit_token, pool).await?;
+    Ok(query::get_clip(shortcode, pool).await?.try_into()?)
+}
+
+/// Fetches the file attached to a clip, checked and counted like reading the clip itself.
+pub async fn get_attachment(
+    req: ask::GetClip,
+    pool: &DatabasePool,
+) -> Result<AttachmentData, ServiceError> {
+    let clip = check_password(req, pool).await?;
+    read_attachment(clip, true, pool).await
+}
+
+/// Fetches the file attached to a clip whose password the caller proved through `unlock`.
+pub async fn get_unlocked_attachment(
+    req: ask::GetClip,
+    unlock: &Unlock,
+    pool: &DatabasePool,
+) -> Result<AttachmentData, ServiceError> {
+    let clip = check_unlock(req, unlock, pool).await?;
+    read_attachment(clip, true, pool).await
+}
+
+/// Fetches the file attached to a clip for its owner, leaving any view limit untouched.
+pub async fn get_owned_attachment(
+    shortcode: Shortcode,
+    edit_token: &field::EditToken,
+    pool: &DatabasePool,
+) -> Result<AttachmentData, ServiceError> {
+    let clip = get_owned_clip(shortcode, edit_token, pool).await?;
+    read_attachment(clip, false, pool).await
+}
+
+/// Loads the attachment before using up a view, the last view deletes it with the clip.
+async fn read_attachment(
+    clip: Clip,
+    counted: bool,
+    pool: &DatabasePool,
+) -> Result<AttachmentData, ServiceError> {
+    if clip.attachment.is_none() {
+        return Err(ServiceError::NotFound);
     }
+    let attachment = query::get_attachment(&clip.shortcode, pool)
+        .await?
+        .try_into()?;
+    if counted {
+        consume_view(clip, pool).await?;
+    }
+    Ok(attachment)
+}
+
+/// Counts a read against the view limit of the clip, if it has one.
+///
+/// Done straight in the database rather than through the `HitCounter` so the clip
+/// is gone the moment its last view is used.
+async fn consume_view(mut clip: Clip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
+    if clip.max_views.is_limited() {
+        let views_left = query::consume_view(&clip.shortcode, pool).await?;
+        clip.max_views = field::MaxViews::new(u32::try_from(views_left).ok());
+    }
+    Ok(clip)
+}
+
+/// New shortcodes tried before giving up, each one after a collision.
+const SHORTCODE_ATTEMPTS: usize = 5;
+
+const SEARCH_LIMIT: u32 = 25;
+const MAX_SEARCH_LIMIT: u32 = 100;
+
+pub async fn search_clips(
+    req: ask::SearchClips,
+    pool: &DatabasePool,
+) -> Result<Vec<SearchResult>, ServiceError> {
+    let limit = req.limit.unwrap_or(SEARCH_LIMIT).min(MAX_SEARCH_LIMIT);
+    Ok(query::search_clips(req.query.as_str(), limit, pool)
+        .await?
+        .into_iter()
+        .map(SearchResult::from)
+        .collect())
 }
 
 pub async fn new_clip(req: ask::NewClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
-    Ok(query::new_clip(req, pool).await?.try_into()?)
+    create_clip(req, None, pool).await
+}
+
+/// Creates a clip through the API, counted against the quotas of `api_key`.
+pub async fn new_api_clip(
+    req: ask::NewClip,
+    api_key: &ApiKey,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    create_clip(req, Some(api_key), pool).await
+}
+
+async fn create_clip(
+    mut req: ask::NewClip,
+    api_key: Option<&ApiKey>,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    let limits = limits::current();
+    req.expires.validate(limits.max_lifetime)?;
+    req.content.validate(limits.content_bytes())?;
+    req.title.validate(limits.title_chars())?;
+    req.max_views.validate()?;
+    let requested = req.shortcode.clone();
+    if let Some(shortcode) = &requested {
+        shortcode.check_requested()?;
+    }
+    if !req.language.is_set() {
+        req.language = field::Language::detect(req.content.as_str());
+    }
+    req.password = req.password.hash()?;
+    let edit_token = field::EditToken::new();
+    let mut model = model::NewClip::from(req)
+        .with_edit_token(edit_token.hash()?)
+        .with_api_key(api_key.map(ApiKey::prefix))
+        .with_quota(api_key.and_then(quota));
+    for _ in 0..SHORTCODE_ATTEMPTS {
+        match query::new_clip(model.clone(), pool).await {
+            Err(DataError::ShortcodeTaken) => match &requested {
+                Some(shortcode) => {
+                    return Err(ServiceError::Conflict(format!(
+                        "shortcode '{}' is already taken",
+                        shortcode.as_str()
+                    )))
+                }
+                None => model = model.with_shortcode(Shortcode::new()),
+            },
+            created => {
+                let mut clip: Clip = created?.try_into()?;
+                clip.edit_token = Some(edit_token);
+                return Ok(clip);
+            }
+        }
+    }
+    Err(DataError::ShortcodeTaken.into())
 }
 
 pub async fn update_clip(req: ask::UpdateClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
-    Ok(query::update_clip(req, pool).await?.try_into()?)
+    apply_update(req, None, pool).await
+}
+
+/// Updates a clip through the API, the new content counts against the quotas of `api_key`.
+pub async fn update_api_clip(
+    req: ask::UpdateClip,
+    api_key: &ApiKey,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    apply_update(req, Some(api_key), pool).await
+}
+
+async fn apply_update(
+    mut req: ask::UpdateClip,
+    api_key: Option<&ApiKey>,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    check_owner(&req.shortcode, &req.edit_token, pool).await?;
+    let limits = limits::current();
+    req.expires.validate(limits.max_lifetime)?;
+    req.content.validate(limits.content_bytes())?;
+    req.title.validate(limits.title_chars())?;
+    if let Some(revision) = req.restore {
+        let revision: Revision = query::get_revision(&req.shortcode, revision, pool)
+            .await?
+            .try_into()?;
+        req.content = revision.content;
+        req.title = revision.title;
+    }
+    if !req.language.is_set() {
+        req.language = field::Language::detect(req.content.as_str());
+    }
+    req.password = req.password.hash()?;
+    let model = model::UpdateClip::from(req).with_quota(api_key.and_then(quota));
+    Ok(query::update_clip(model, pool).await?.try_into()?)
+}
+
+/// Lists the earlier versions of a clip, newest first. Only its owner may see them.
+pub async fn get_revisions(
+    shortcode: &Shortcode,
+    edit_token: &field::EditToken,
+    pool: &DatabasePool,
+) -> Result<Vec<Revision>, ServiceError> {
+    check_owner(shortcode, edit_token, pool).await?;
+    query::get_revisions(shortcode, pool)
+        .await?
+        .into_iter()
+        .map(|revision| Ok(revision.try_into()?))
+        .collect()
+}
+
+pub async fn get_revision(
+    shortcode: &Shortcode,
+    revision: u32,
+    edit_token: &field::EditToken,
+    pool: &DatabasePool,
+) -> Result<Revision, ServiceError> {
+    check_owner(shortcode, edit_token, pool).await?;
+    Ok(query::get_revision(shortcode, revision, pool)
+        .await?
+        .try_into()?)
+}
+
+pub async fn delete_clip(req: ask::DeleteClip, pool: &DatabasePool) -> Result<(), ServiceError> {
+    let get_req = ask::GetClip {
+        shortcode: req.shortcode.clone(),
+        password: req.password,
+    };
+    check_password(get_req, pool).await?;
+    delete_owned_clip(req.shortcode, &req.edit_token, pool).await
+}
+
+/// Deletes a clip whose password the caller proved through `unlock`, ownership is still
+/// checked.
+pub async fn delete_unlocked_clip(
+    req: ask::DeleteClip,
+    unlock: &Unlock,
+    pool: &DatabasePool,
+) -> Result<(), ServiceError> {
+    let get_req = ask::GetClip {
+        shortcode: req.shortcode.clone(),
+        password: req.password,
+    };
+    check_unlock(get_req, unlock, pool).await?;
+    delete_owned_clip(req.shortcode, &req.edit_token, pool).await
+}
+
+async fn delete_owned_clip(
+    shortcode: Shortcode,
+    edit_token: &field::EditToken,
+    pool: &DatabasePool,
+) -> Result<(), ServiceError> {
+    check_owner(&shortcode, edit_token, pool).await?;
+    match query::delete_clip(&shortcode, pool).await? {
+        0 => Err(ServiceError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+/// The configured quotas of `api_key`, enforced by the query that stores its clip.
+///
+/// An edit keeps the replaced content as a revision and leaves the attachment in place,
+/// so all of it still counts against the key.
+fn quota(api_key: &ApiKey) -> Option<model::Quota> {
+    let limits = limits::current();
+    if limits.max_clips_per_key.is_none() && limits.max_bytes_per_key.is_none() {
+        return None;
+    }
+    Some(model::Quota::new(
+        api_key.prefix(),
+        limits.max_clips_per_key,
+        limits.max_bytes_per_key,
+    ))
+}
+
+async fn check_owner(
+    shortcode: &Shortcode,
+    edit_token: &field::EditToken,
+    pool: &DatabasePool,
+) -> Result<(), ServiceError> {
+    let owner = query::get_edit_token(shortcode, pool).await?;
+    if edit_token.verify(owner.as_deref()) {
+        Ok(())
+    } else {
+        Err(ServiceError::PermissionError(
+            "Invalid edit token".to_owned(),
+        ))
+    }
 }
 
 pub async fn begin_transaction(pool: &DatabasePool) -> Result<Transaction<'_>, ServiceError> {
@@ -37,14 +379,58 @@ pub async fn end_transaction(transaction: Transaction<'_>) -> Result<(), Service
 pub async fn increase_hits_count(
     shortcode: &Shortcode,
     hits: u32,
-    pool: &DatabasePool,
+    transaction: &mut Transaction<'_>,
 ) -> Result<(), ServiceError> {
-    Ok(query::increase_hit_count(shortcode, hits, pool).await?)
+    Ok(query::increase_hit_count(shortcode, hits, transaction).await?)
+}
+
+/// Records views collected by the `HitCounter` in the hourly `bucket`.
+pub async fn record_views(
+    shortcode: &Shortcode,
+    bucket: i64,
+    source: &ViewSource,
+    views: u32,
+    transaction: &mut Transaction<'_>,
+) -> Result<(), ServiceError> {
+    Ok(query::record_views(shortcode, bucket, source, views, transaction).await?)
+}
+
+/// Views of a clip over the window of `period`. Only its owner may see them.
+pub async fn get_view_history(
+    shortcode: &Shortcode,
+    period: ViewPeriod,
+    edit_token: &field::EditToken,
+    pool: &DatabasePool,
+) -> Result<ViewHistory, ServiceError> {
+    check_owner(shortcode, edit_token, pool).await?;
+    let since = period.bucket((Utc::now() - period.window()).timestamp());
+    let buckets = query::view_buckets(shortcode, period.seconds(), since, pool).await?;
+    let referrers = query::view_counts(shortcode, since, false, pool).await?;
+    let agents = query::view_counts(shortcode, since, true, pool).await?;
+    Ok(ViewHistory {
+        period,
+        buckets: buckets.into_iter().map(Into::into).collect(),
+        referrers: referrers.into_iter().map(Into::into).collect(),
+        agents: agents.into_iter().map(Into::into).collect(),
+    })
 }
 
-pub async fn generate_api_key(pool: &DatabasePool) -> Result<ApiKey, ServiceError> {
+pub async fn generate_api_key(
+    req: ask::NewApiKey,
+    pool: &DatabasePool,
+) -> Result<(ApiKey, ApiKeyInfo), ServiceError> {
     let api_key = ApiKey::default();
-    Ok(query::save_api_key(api_key, pool).await?)
+    query::save_api_key(model::NewApiKey::new(&api_key, req), pool).await?;
+    let info = query::get_api_key(&api_key.prefix(), pool).await?.into();
+    Ok((api_key, info))
+}
+
+pub async fn list_api_keys(pool: &DatabasePool) -> Result<Vec<ApiKeyInfo>, ServiceError> {
+    Ok(query::list_api_keys(pool)
+        .await?
+        .into_iter()
+        .map(ApiKeyInfo::from)
+        .collect())
 }
 
 pub async fn revoke_api_key(
@@ -54,10 +440,47 @@ pub async fn revoke_api_key(
     Ok(query::revoke_api_key(api_key, pool).await?)
 }
 
-pub async fn api_key_is_valid(api_key: ApiKey, pool: &DatabasePool) -> Result<bool, ServiceError> {
-    Ok(query::api_key_is_valid(api_key, pool).await?)
+/// Revokes a key knowing only its prefix, e.g. one found with [`list_api_keys`].
+pub async fn revoke_api_key_by_prefix(
+    prefix: &str,
+    pool: &DatabasePool,
+) -> Result<query::RevocationStatus, ServiceError> {
+    Ok(query::revoke_api_key_by_prefix(prefix, pool).await?)
+}
+
+/// Looks a key up by its prefix and checks the digest of the rest in constant time.
+pub async fn get_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<ApiKeyInfo, ServiceError> {
+    let stored = query::get_api_key(&api_key.prefix(), pool).await?;
+    if bool::from(stored.digest().ct_eq(&api_key.digest())) {
+        Ok(stored.into())
+    } else {
+        Err(ServiceError::NotFound)
+    }
+}
+
+/// Records that the key was just used to authenticate a request.
+pub async fn touch_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<(), ServiceError> {
+    Ok(query::touch_api_key(&api_key.prefix(), pool).await?)
 }
 
 pub async fn delete_expires(pool: &DatabasePool) -> Result<u64, ServiceError> {
     Ok(query::delete_expired(pool).await?)
 }
+
+/// How long an unreferenced blob is kept, so saves still in progress are left alone.
+const BLOB_GRACE_MINUTES: i64 = 60;
+
+/// Deletes blobs no clip, revision or attachment refers to any more. Does nothing when
+/// no blob store is configured.
+pub async fn collect_garbage(pool: &DatabasePool) -> Result<u64, ServiceError> {
+    let storage = match Storage::current() {
+        Some(storage) => storage,
+        None => return Ok(0),
+    };
+    let referenced = query::blob_keys(pool).await?;
+    let cutoff = Utc::now() - chrono::Duration::minutes(BLOB_GRACE_MINUTES);
+    Ok(storage
+        .collect_garbage(&referenced, cutoff)
+        .await
+        .map_err(DataError::from)?)
+}
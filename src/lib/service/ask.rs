@@ -1,5 +1,6 @@
-use crate::domain::clip::field;
-use crate::Shortcode;
+use crate::domain::api_key::Scopes;
+use crate::domain::clip::{field, AttachmentData};
+use crate::{Shortcode, Time};
 
 use derive_more::Constructor;
 use serde::{Deserialize, Serialize};
@@ -40,6 +41,19 @@ pub struct NewClip {
     pub title: field::Title,
     pub expires: field::Expires,
     pub password: field::Password,
+    #[serde(default)]
+    pub max_views: field::MaxViews,
+    /// Vanity shortcode chosen by the creator, a random one is generated when `None`.
+    #[serde(default)]
+    pub shortcode: Option<field::Shortcode>,
+    /// Detected from the content when not set.
+    #[serde(default)]
+    pub language: field::Language,
+    #[serde(default)]
+    pub format: field::Format,
+    /// File uploaded along with the clip, only accepted from multipart forms.
+    #[serde(skip)]
+    pub attachment: Option<AttachmentData>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -49,4 +63,34 @@ pub struct UpdateClip {
     pub expires: field::Expires,
     pub password: field::Password,
     pub shortcode: field::Shortcode,
+    pub edit_token: field::EditToken,
+    /// Replace content and title with those of this earlier revision.
+    #[serde(default)]
+    pub restore: Option<u32>,
+    /// Detected from the content when not set.
+    #[serde(default)]
+    pub language: field::Language,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SearchClips {
+    pub query: String,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteClip {
+    pub shortcode: Shortcode,
+    pub password: field::Password,
+    pub edit_token: field::EditToken,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewApiKey {
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub expires: Option<Time>,
+    #[serde(default)]
+    pub scopes: Scopes,
 }
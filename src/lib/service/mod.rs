@@ -13,6 +13,10 @@ pub enum ServiceError {
     NotFound,
     #[error("Permission not met {0}")]
     PermissionError(String),
+    #[error("quota exceeded: {0}")]
+    QuotaExceeded(String),
+    #[error("conflict: {0}")]
+    Conflict(String),
 }
 
 impl From<DataError> for ServiceError {
@@ -22,6 +26,8 @@ impl From<DataError> for ServiceError {
                 sqlx::Error::RowNotFound => Self::NotFound,
                 other => Self::Data(DataError::Database(other)),
             },
+            DataError::QuotaExceeded(msg) => Self::QuotaExceeded(msg),
+            other => Self::Data(other),
         }
     }
 }
@@ -0,0 +1,341 @@
+//! Errors sent by the API.
+//!
+//! Every error, whether returned by a route or sent by a catcher, has the same JSON body:
+//! a stable machine-readable `code`, a human readable `message` and optional `details`.
+
+use super::ApiKeyError;
+use crate::domain::clip::ClipError;
+use crate::domain::limits;
+use crate::ServiceError;
+use rocket::http::Status;
+use rocket::request::{Outcome, Request};
+use rocket::serde::json::{self, Json};
+use rocket::Responder;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    /// Stable identifier of the error, such as `content_too_long`.
+    pub code: String,
+    pub message: String,
+    /// Extra data for some codes, such as the limit that was exceeded.
+    pub details: Option<Value>,
+}
+
+impl ErrorBody {
+    pub fn new<M: Into<String>>(code: &str, message: M) -> Self {
+        Self {
+            code: code.to_owned(),
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Generic body for a status, used when nothing more specific is known. The code is
+    /// the reason phrase in snake case, such as `not_found`.
+    pub fn of_status(status: Status) -> Self {
+        let reason = status.reason_lossy().to_lowercase();
+        Self::new(&reason.replace(' ', "_"), reason)
+    }
+}
+
+#[derive(Responder, Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{}", .0.message)]
+    #[response(status = 404, content_type = "json")]
+    NotFound(Json<ErrorBody>),
+
+    #[error("{}", .0.message)]
+    #[response(status = 500, content_type = "json")]
+    Server(Json<ErrorBody>),
+
+    #[error("{}", .0.message)]
+    #[response(status = 401, content_type = "json")]
+    User(Json<ErrorBody>),
+
+    #[error("{}", .0.message)]
+    #[response(status = 400, content_type = "json")]
+    BadRequest(Json<ErrorBody>),
+
+    #[error("{}", .0.message)]
+    #[response(status = 403, content_type = "json")]
+    Forbidden(Json<ErrorBody>),
+
+    #[error("{}", .0.message)]
+    #[response(status = 429, content_type = "json")]
+    TooManyRequests(Json<ErrorBody>),
+
+    #[error("{}", .0.message)]
+    #[response(status = 409, content_type = "json")]
+    Conflict(Json<ErrorBody>),
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            Self::NotFound(_) => Status::NotFound,
+            Self::Server(_) => Status::InternalServerError,
+            Self::User(_) => Status::Unauthorized,
+            Self::BadRequest(_) => Status::BadRequest,
+            Self::Forbidden(_) => Status::Forbidden,
+            Self::TooManyRequests(_) => Status::TooManyRequests,
+            Self::Conflict(_) => Status::Conflict,
+        }
+    }
+
+    pub fn body(&self) -> &ErrorBody {
+        match self {
+            Self::NotFound(body)
+            | Self::Server(body)
+            | Self::User(body)
+            | Self::BadRequest(body)
+            | Self::Forbidden(body)
+            | Self::TooManyRequests(body)
+            | Self::Conflict(body) => body,
+        }
+    }
+
+    /// The error hidden behind a generic message, for failures the client can do nothing about.
+    pub fn server() -> Self {
+        Self::Server(Json(ErrorBody::of_status(Status::InternalServerError)))
+    }
+
+    pub fn invalid_body(err: json::Error<'_>) -> Self {
+        Self::BadRequest(Json(ErrorBody::new(
+            "invalid_body",
+            format!("invalid request body: {}", err),
+        )))
+    }
+
+    /// Like [`invalid_body`](Self::invalid_body) for a body carrying clip content. The
+    /// JSON limit fits the longest content escaped, so a body cut off at the limit is
+    /// reported as content that is too long.
+    pub fn invalid_clip_body(err: json::Error<'_>) -> Self {
+        match err {
+            json::Error::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                ClipError::ContentTooLong(limits::current().content_bytes()).into()
+            }
+            err => Self::invalid_body(err),
+        }
+    }
+}
+
+impl From<ServiceError> for ApiError {
+    fn from(err: ServiceError) -> Self {
+        match err {
+            ServiceError::Clip(e) => e.into(),
+            ServiceError::NotFound => {
+                Self::NotFound(Json(ErrorBody::new("not_found", "entity not found")))
+            }
+            ServiceError::Data(e) => {
+                eprintln!("data error: {}", e);
+                Self::server()
+            }
+            ServiceError::PermissionError(msg) => {
+                Self::User(Json(ErrorBody::new("permission_denied", msg)))
+            }
+            // Stored clips do not go away with time, so this is not a 429 a client would
+            // retry after waiting.
+            ServiceError::QuotaExceeded(msg) => {
+                Self::Forbidden(Json(ErrorBody::new("quota_exceeded", msg)))
+            }
+            ServiceError::Conflict(msg) => Self::Conflict(Json(ErrorBody::new("conflict", msg))),
+        }
+    }
+}
+
+impl From<ClipError> for ApiError {
+    fn from(err: ClipError) -> Self {
+        let (code, details) = match &err {
+            ClipError::InvalidPassword(_) => ("invalid_password", None),
+            ClipError::InvalidShortcode(_) => ("invalid_shortcode", None),
+            ClipError::InvalidFormat(_) => ("invalid_format", None),
+            ClipError::InvalidLanguage(_) => ("invalid_language", None),
+            ClipError::InvalidTitle(_) => ("invalid_title", None),
+            ClipError::InvalidMaxViews(_) => ("invalid_max_views", None),
+            ClipError::InvalidAttachment(_) => ("invalid_attachment", None),
+            ClipError::EmptyContent => ("empty_content", None),
+            ClipError::ContentTooLong(max) => {
+                ("content_too_long", Some(json!({ "max_bytes": max })))
+            }
+            ClipError::TitleTooLong(max) => ("title_too_long", Some(json!({ "max_chars": max }))),
+            ClipError::InvalidDate(_) | ClipError::DateParse(_) => ("invalid_date", None),
+            // Raised on our side or by stored data that cannot be read, not by the request.
+            ClipError::PasswordHash(_)
+            | ClipError::Decompress(_)
+            | ClipError::Id(_)
+            | ClipError::Hits(_) => {
+                eprintln!("clip error: {}", err);
+                return Self::server();
+            }
+        };
+        Self::BadRequest(Json(ErrorBody {
+            code: code.to_owned(),
+            message: err.to_string(),
+            details,
+        }))
+    }
+}
+
+impl From<ApiKeyError> for ApiError {
+    fn from(err: ApiKeyError) -> Self {
+        let message = err.to_string();
+        match err {
+            ApiKeyError::NotFound => Self::User(Json(ErrorBody::new("api_key_not_found", message))),
+            ApiKeyError::DecodeError(_) => {
+                Self::User(Json(ErrorBody::new("invalid_api_key", message)))
+            }
+            ApiKeyError::Expired => Self::User(Json(ErrorBody::new("api_key_expired", message))),
+            ApiKeyError::MissingScope(scope) => Self::Forbidden(Json(
+                ErrorBody::new("missing_scope", message)
+                    .with_details(json!({ "scope": scope.to_string() })),
+            )),
+        }
+    }
+}
+
+/// Body of the error a guard turned the request away with. Catchers only get the status of
+/// a failed guard, so the body is kept in the request for them.
+#[derive(Default)]
+pub(super) struct Rejection(pub(super) Option<ErrorBody>);
+
+/// Fails a request guard with `err`, keeping its body for the catcher.
+pub(super) fn reject<T>(req: &Request<'_>, err: ApiError) -> Outcome<T, ApiError> {
+    req.local_cache(|| Rejection(Some(err.body().clone())));
+    Outcome::Failure((err.status(), err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::DataError;
+    use crate::domain::api_key::Scope;
+
+    fn mapped<E: Into<ApiError>>(err: E) -> (Status, String) {
+        let err = err.into();
+        (err.status(), err.body().code.clone())
+    }
+
+    #[test]
+    fn service_errors_have_stable_codes() {
+        let cases = [
+            (ServiceError::NotFound, Status::NotFound, "not_found"),
+            (
+                ServiceError::PermissionError("Invalid password".to_owned()),
+                Status::Unauthorized,
+                "permission_denied",
+            ),
+            (
+                ServiceError::QuotaExceeded("too many clips".to_owned()),
+                Status::Forbidden,
+                "quota_exceeded",
+            ),
+            (
+                ServiceError::Conflict("shortcode taken".to_owned()),
+                Status::Conflict,
+                "conflict",
+            ),
+            (
+                ServiceError::Clip(ClipError::EmptyContent),
+                Status::BadRequest,
+                "empty_content",
+            ),
+        ];
+        for (err, status, code) in cases {
+            assert_eq!(mapped(err), (status, code.to_owned()));
+        }
+
+        let err = ApiError::from(ServiceError::Data(DataError::Database(
+            sqlx::Error::PoolClosed,
+        )));
+        assert_eq!(
+            err.body(),
+            &ErrorBody::of_status(Status::InternalServerError)
+        );
+    }
+
+    #[test]
+    fn clip_errors_have_stable_codes() {
+        let client_errors = [
+            (ClipError::InvalidPassword("x".into()), "invalid_password"),
+            (ClipError::InvalidShortcode("x".into()), "invalid_shortcode"),
+            (ClipError::InvalidFormat("x".into()), "invalid_format"),
+            (ClipError::InvalidLanguage("x".into()), "invalid_language"),
+            (ClipError::InvalidTitle("x".into()), "invalid_title"),
+            (ClipError::InvalidMaxViews("x".into()), "invalid_max_views"),
+            (
+                ClipError::InvalidAttachment("x".into()),
+                "invalid_attachment",
+            ),
+            (ClipError::EmptyContent, "empty_content"),
+            (ClipError::ContentTooLong(100), "content_too_long"),
+            (ClipError::TitleTooLong(5), "title_too_long"),
+            (ClipError::InvalidDate("x".into()), "invalid_date"),
+        ];
+        for (err, code) in client_errors {
+            assert_eq!(mapped(err), (Status::BadRequest, code.to_owned()));
+        }
+
+        let server_errors = [
+            ClipError::PasswordHash("x".into()),
+            ClipError::Decompress("x".into()),
+            ClipError::Id(uuid::Uuid::parse_str("x").unwrap_err()),
+            ClipError::Hits(u8::try_from(300).unwrap_err()),
+        ];
+        for err in server_errors {
+            let err = ApiError::from(err);
+            assert_eq!(err.status(), Status::InternalServerError);
+            assert_eq!(
+                err.body(),
+                &ErrorBody::of_status(Status::InternalServerError)
+            );
+        }
+
+        let err = ApiError::from(ClipError::ContentTooLong(100));
+        assert_eq!(err.body().details, Some(json!({ "max_bytes": 100 })));
+    }
+
+    #[test]
+    fn api_key_errors_have_stable_codes() {
+        let cases = [
+            (
+                ApiKeyError::NotFound,
+                Status::Unauthorized,
+                "api_key_not_found",
+            ),
+            (
+                ApiKeyError::DecodeError("bad".into()),
+                Status::Unauthorized,
+                "invalid_api_key",
+            ),
+            (
+                ApiKeyError::Expired,
+                Status::Unauthorized,
+                "api_key_expired",
+            ),
+            (
+                ApiKeyError::MissingScope(Scope::Admin),
+                Status::Forbidden,
+                "missing_scope",
+            ),
+        ];
+        for (err, status, code) in cases {
+            assert_eq!(mapped(err), (status, code.to_owned()));
+        }
+    }
+
+    #[test]
+    fn body_has_code_message_and_details() {
+        let body = serde_json::to_value(ErrorBody::of_status(Status::NotFound)).unwrap();
+        assert_eq!(
+            body,
+            json!({ "code": "not_found", "message": "not found", "details": null })
+        );
+    }
+}
@@ -1,30 +1,45 @@
 use crate::data::AppDatabase;
-use crate::domain::clip::field::Password;
+use crate::domain::api_key::{self, ApiKeyInfo, Scope};
+use crate::domain::clip::field::{EditToken, Password};
+use crate::domain::clip::{Revision, SearchResult};
+use crate::domain::views::{ViewHistory, ViewPeriod, ViewSource};
 use crate::service;
 use crate::service::action;
-use crate::web::api::ApiError::Server;
-use crate::web::{counter, HitCounter, PASSWORD_COOKIE};
-use crate::ServiceError;
+use crate::web::attachment::AttachmentFile;
+use crate::web::rate_limit::{RateLimiter, RetryAfter};
+use crate::web::{form, HitCounter, UnlockToken};
+use crate::{ServiceError, Shortcode};
 use base64::engine;
+use rocket::data::{self, Data, FromData};
+use rocket::form::Form;
 use rocket::futures::future::ok;
-use rocket::http::{CookieJar, Status};
+use rocket::http::{CookieJar, Method, Status};
 use rocket::request::{FromRequest, Outcome, Request};
-use rocket::serde::json::Json;
-use rocket::Responder;
+use rocket::serde::json::{self, Json};
 use rocket::State;
 use serde::Serialize;
+use serde_json::json;
 use std::str::FromStr;
 
+mod error;
+
+use error::reject;
+pub use error::{ApiError, ErrorBody};
+
 pub const API_KEY_HEADER: &str = "x-api-key";
+pub const PASSWORD_HEADER: &str = "x-clip-password";
+pub const EDIT_TOKEN_HEADER: &str = "x-edit-token";
 
-#[derive(Responder, Debug, thiserror::Error, Serialize)]
+#[derive(Debug, thiserror::Error)]
 pub enum ApiKeyError {
     #[error("API key not found")]
-    #[response(status = 404, content_type = "json")]
-    NotFound(String),
-    #[response(status = 400, content_type = "json")]
-    #[error("invalid API key format")]
+    NotFound,
+    #[error("invalid API key format: {0}")]
     DecodeError(String),
+    #[error("API key expired")]
+    Expired,
+    #[error("API key lacks the '{0}' scope")]
+    MissingScope(Scope),
 }
 
 #[derive(Debug, Clone)]
@@ -39,11 +54,19 @@ impl ApiKey {
     pub fn into_inner(self) -> Vec<u8> {
         self.0
     }
+
+    pub fn prefix(&self) -> String {
+        api_key::prefix(&self.0)
+    }
+
+    pub fn digest(&self) -> Vec<u8> {
+        api_key::digest(&self.0)
+    }
 }
 
 impl Default for ApiKey {
     fn default() -> Self {
-        let key = (0..16).map(|_| rand::random::<u8>()).collect();
+        let key = (0..32).map(|_| rand::random::<u8>()).collect();
         Self(key)
     }
 }
@@ -57,166 +80,574 @@ impl FromStr for ApiKey {
     }
 }
 
-#[derive(Responder, Debug, thiserror::Error)]
-pub enum ApiError {
-    #[error("not found")]
-    #[response(status = 404, content_type = "json")]
-    NotFound(Json<String>),
+/// Checks the key in the [`API_KEY_HEADER`] header against the database and makes sure it
+/// has not expired and carries `scope`.
+async fn authorize(req: &Request<'_>, scope: Scope) -> Outcome<ApiKey, ApiError> {
+    let server_error = || reject(req, ApiError::server());
+    let key_error = |e: ApiKeyError| reject(req, e.into());
+    let key = match req.headers().get_one(API_KEY_HEADER) {
+        None => return key_error(ApiKeyError::NotFound),
+        Some(key) => key,
+    };
+    let db = match req.guard::<&State<AppDatabase>>().await {
+        Outcome::Success(db) => db,
+        _ => return server_error(),
+    };
+    let api_key = match ApiKey::from_str(key) {
+        Ok(key) => key,
+        Err(e) => return key_error(e),
+    };
+    let info = match action::get_api_key(api_key.clone(), db.get_pool()).await {
+        Ok(info) => info,
+        Err(ServiceError::NotFound) => return key_error(ApiKeyError::NotFound),
+        Err(_) => return server_error(),
+    };
+    if info.is_expired() {
+        return key_error(ApiKeyError::Expired);
+    }
+    if !info.allows(scope) {
+        return key_error(ApiKeyError::MissingScope(scope));
+    }
+    let limiter = match req.guard::<&State<RateLimiter>>().await {
+        Outcome::Success(limiter) => limiter,
+        _ => return server_error(),
+    };
+    if let Err(wait) = limiter.check_api_key(&info.prefix) {
+        RetryAfter::remember(req, wait);
+        return reject(
+            req,
+            ApiError::TooManyRequests(Json(ErrorBody::of_status(Status::TooManyRequests))),
+        );
+    }
+    match action::touch_api_key(api_key.clone(), db.get_pool()).await {
+        Ok(()) => Outcome::Success(api_key),
+        Err(_) => server_error(),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = ApiError;
 
-    #[error("server found")]
-    #[response(status = 500, content_type = "json")]
-    Server(Json<String>),
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(req, required_scope(req.method())).await
+    }
+}
 
-    #[error("client found")]
-    #[response(status = 401, content_type = "json")]
-    User(Json<String>),
+/// API key carrying the `admin` scope, required to manage other keys.
+pub struct AdminApiKey(ApiKey);
 
-    #[error("key found")]
-    #[response(status = 400, content_type = "json")]
-    Key(Json<ApiKeyError>),
+impl AdminApiKey {
+    pub fn into_inner(self) -> ApiKey {
+        self.0
+    }
 }
 
-impl From<ServiceError> for ApiError {
-    fn from(err: ServiceError) -> Self {
-        match err {
-            ServiceError::Clip(c) => Self::User(Json(format!("clip parsing error: {}", c))),
-            ServiceError::NotFound => Self::NotFound(Json("entity not found".to_owned())),
-            ServiceError::Data(_) => Server(Json("a server error occurred".to_owned())),
-            ServiceError::PermissionError(msg) => Self::User(Json(msg)),
-        }
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminApiKey {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(req, Scope::Admin).await.map(AdminApiKey)
     }
 }
 
+/// Reading clips needs a `read` key, anything that changes them a `write` key.
+fn required_scope(method: Method) -> Scope {
+    match method {
+        Method::Get | Method::Head | Method::Options => Scope::Read,
+        _ => Scope::Write,
+    }
+}
+
+/// Clip password sent by non-browser clients in the [`PASSWORD_HEADER`] header.
+pub struct ClipPassword(Password);
+
 #[rocket::async_trait]
-impl<'r> FromRequest<'r> for ApiKey {
+impl<'r> FromRequest<'r> for ClipPassword {
     type Error = ApiError;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        fn server_error() -> Outcome<ApiKey, ApiError> {
-            Outcome::Failure((
-                Status::InternalServerError,
-                Server(Json("server error".to_string())),
-            ))
-        }
-        fn key_error(e: ApiKeyError) -> Outcome<ApiKey, ApiError> {
-            Outcome::Failure((Status::BadRequest, ApiError::Key(Json(e))))
-        }
-        match req.headers().get_one(API_KEY_HEADER) {
-            None => key_error(ApiKeyError::NotFound("API key not found".to_string())),
-            Some(key) => {
-                let db = match req.guard::<&State<AppDatabase>>().await {
-                    Outcome::Success(db) => db,
-                    _ => return server_error(),
-                };
-                let api_key = match ApiKey::from_str(key) {
-                    Ok(key) => key,
-                    Err(e) => return key_error(e),
-                };
-                match action::api_key_is_valid(api_key.clone(), db.get_pool()).await {
-                    Ok(valid) if valid => Outcome::Success(api_key),
-                    Ok(valid) if !valid => {
-                        key_error(ApiKeyError::NotFound("API key not found".to_string()))
-                    }
-                    _ => server_error(),
-                }
-            }
+        let password = req
+            .headers()
+            .get_one(PASSWORD_HEADER)
+            .and_then(|raw_pswd| Password::new(raw_pswd.to_string()).ok())
+            .unwrap_or_default();
+        Outcome::Success(ClipPassword(password))
+    }
+}
+
+/// Edit token of the clip owner, sent in the [`EDIT_TOKEN_HEADER`] header.
+pub struct ClipEditToken(EditToken);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClipEditToken {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req
+            .headers()
+            .get_one(EDIT_TOKEN_HEADER)
+            .and_then(|raw| EditToken::from_str(raw).ok())
+        {
+            Some(edit_token) => Outcome::Success(ClipEditToken(edit_token)),
+            None => reject(
+                req,
+                ApiError::User(Json(ErrorBody::new(
+                    "missing_edit_token",
+                    "missing edit token",
+                ))),
+            ),
         }
     }
 }
 
-#[rocket::get("/key")]
-pub async fn new_api_key(db: &State<AppDatabase>) -> Result<Json<&str>, ApiError> {
-    //TODO learn ? operator on this example
-    let new_key = action::generate_api_key(db.get_pool()).await?;
-    println!("API: {}", new_key.to_base64());
-    Ok(Json("Api key generated. See logs for details"))
+/// A freshly issued key. This is the only time the full key is shown.
+#[derive(Debug, Serialize)]
+pub struct IssuedApiKey {
+    pub key: String,
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+}
+
+#[rocket::post("/key", data = "<req>")]
+pub async fn new_api_key(
+    req: Result<Json<service::ask::NewApiKey>, json::Error<'_>>,
+    db: &State<AppDatabase>,
+    _admin: AdminApiKey,
+) -> Result<Json<IssuedApiKey>, ApiError> {
+    let req = req.map_err(ApiError::invalid_body)?.into_inner();
+    let (key, info) = action::generate_api_key(req, db.get_pool()).await?;
+    Ok(Json(IssuedApiKey {
+        key: key.to_base64(),
+        info,
+    }))
 }
 
 #[rocket::get("/<shortcode>")]
 pub async fn get_clip(
-    shortcode: &str,
+    shortcode: Shortcode,
     db: &State<AppDatabase>,
-    cookie: &CookieJar<'_>,
+    cookies: &CookieJar<'_>,
+    password: ClipPassword,
     hit_counter: &State<HitCounter>,
+    source: ViewSource,
     _api_key: ApiKey,
 ) -> Result<Json<crate::Clip>, ApiError> {
-    use crate::domain::clip::field::Password;
     let req = service::ask::GetClip {
-        shortcode: shortcode.into(),
-        password: cookie
-            .get(PASSWORD_COOKIE)
-            .map(|cookie| cookie.value())
-            .and_then(|raw_pswd| Password::new(raw_pswd.to_string()).ok())
-            .unwrap_or_default(),
+        shortcode: shortcode.clone(),
+        password: password.0,
     };
-    let clip = action::get_clip(req, db.get_pool()).await?;
-    hit_counter.hit(shortcode.into(), 1);
+    let clip = match UnlockToken::find(&shortcode, cookies) {
+        Some(unlock) => action::get_unlocked_clip(req, &unlock, db.get_pool()).await?,
+        None => action::get_clip(req, db.get_pool()).await?,
+    };
+    hit_counter.hit(shortcode, source);
     Ok(Json(clip))
 }
 
+#[rocket::get("/search?<q>&<limit>")]
+pub async fn search_clips(
+    q: String,
+    limit: Option<u32>,
+    db: &State<AppDatabase>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<SearchResult>>, ApiError> {
+    let req = service::ask::SearchClips { query: q, limit };
+    Ok(Json(action::search_clips(req, db.get_pool()).await?))
+}
+
+#[rocket::get("/<shortcode>/revisions")]
+pub async fn get_revisions(
+    shortcode: Shortcode,
+    db: &State<AppDatabase>,
+    edit_token: ClipEditToken,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<Revision>>, ApiError> {
+    let revisions = action::get_revisions(&shortcode, &edit_token.0, db.get_pool()).await?;
+    Ok(Json(revisions))
+}
+
+#[rocket::get("/<shortcode>/revisions/<revision>")]
+pub async fn get_revision(
+    shortcode: Shortcode,
+    revision: u32,
+    db: &State<AppDatabase>,
+    edit_token: ClipEditToken,
+    _api_key: ApiKey,
+) -> Result<Json<Revision>, ApiError> {
+    let revision = action::get_revision(&shortcode, revision, &edit_token.0, db.get_pool()).await?;
+    Ok(Json(revision))
+}
+
+#[rocket::get("/<shortcode>/views?<period>")]
+pub async fn get_views(
+    shortcode: Shortcode,
+    period: Option<ViewPeriod>,
+    db: &State<AppDatabase>,
+    edit_token: ClipEditToken,
+    _api_key: ApiKey,
+) -> Result<Json<ViewHistory>, ApiError> {
+    let period = period.unwrap_or_default();
+    let history =
+        action::get_view_history(&shortcode, period, &edit_token.0, db.get_pool()).await?;
+    Ok(Json(history))
+}
+
+/// A new clip sent as JSON, or as `multipart/form-data` with the fields of the home page
+/// form when a file is attached.
+pub struct NewClipRequest(service::ask::NewClip);
+
+#[rocket::async_trait]
+impl<'r> FromData<'r> for NewClipRequest {
+    type Error = ApiError;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        fn invalid<'r>(err: ApiError) -> data::Outcome<'r, NewClipRequest> {
+            data::Outcome::Failure((err.status(), err))
+        }
+        let multipart = req
+            .content_type()
+            .map(|content_type| content_type.is_form_data())
+            .unwrap_or(false);
+        if multipart {
+            match Form::<form::NewClip>::from_data(req, data).await {
+                data::Outcome::Success(form) => match form.into_inner().into_request() {
+                    Ok(clip) => data::Outcome::Success(NewClipRequest(clip)),
+                    Err(e) => invalid(e.into()),
+                },
+                data::Outcome::Failure((_, errors)) => {
+                    let fields = errors
+                        .iter()
+                        .map(|e| json!({ "field": e.name.as_ref().map(|name| name.to_string()), "message": e.kind.to_string() }))
+                        .collect::<Vec<_>>();
+                    invalid(ApiError::BadRequest(Json(
+                        ErrorBody::new("invalid_form", format!("form error: {}", errors))
+                            .with_details(json!({ "fields": fields })),
+                    )))
+                }
+                data::Outcome::Forward(data) => data::Outcome::Forward(data),
+            }
+        } else {
+            match Json::<service::ask::NewClip>::from_data(req, data).await {
+                data::Outcome::Success(clip) => {
+                    data::Outcome::Success(NewClipRequest(clip.into_inner()))
+                }
+                data::Outcome::Failure((_, e)) => invalid(ApiError::invalid_clip_body(e)),
+                data::Outcome::Forward(data) => data::Outcome::Forward(data),
+            }
+        }
+    }
+}
+
 #[rocket::post("/", data = "<req>")]
 pub async fn new_clip(
-    req: Json<service::ask::NewClip>,
+    req: Result<NewClipRequest, ApiError>,
     db: &State<AppDatabase>,
-    _api_key: ApiKey,
+    api_key: ApiKey,
 ) -> Result<Json<crate::Clip>, ApiError> {
-    let clip = action::new_clip(req.into_inner(), db.get_pool()).await?;
+    let clip = action::new_api_clip(req?.0, &api_key, db.get_pool()).await?;
     Ok(Json(clip))
 }
 
+#[rocket::get("/<shortcode>/attachment")]
+pub async fn get_attachment(
+    shortcode: Shortcode,
+    db: &State<AppDatabase>,
+    cookies: &CookieJar<'_>,
+    password: ClipPassword,
+    _api_key: ApiKey,
+) -> Result<AttachmentFile, ApiError> {
+    let unlock = UnlockToken::find(&shortcode, cookies);
+    let req = service::ask::GetClip {
+        shortcode,
+        password: password.0,
+    };
+    let file = match unlock {
+        Some(unlock) => action::get_unlocked_attachment(req, &unlock, db.get_pool()).await?,
+        None => action::get_attachment(req, db.get_pool()).await?,
+    };
+    Ok(AttachmentFile::download(file))
+}
+
 #[rocket::put("/", data = "<req>")]
 pub async fn update_clip(
-    req: Json<service::ask::UpdateClip>,
+    req: Result<Json<service::ask::UpdateClip>, json::Error<'_>>,
     db: &State<AppDatabase>,
-    _api_key: ApiKey,
+    api_key: ApiKey,
 ) -> Result<Json<crate::Clip>, ApiError> {
-    let clip = action::update_clip(req.into_inner(), db.get_pool()).await?;
+    let req = req.map_err(ApiError::invalid_clip_body)?.into_inner();
+    let clip = action::update_api_clip(req, &api_key, db.get_pool()).await?;
     Ok(Json(clip))
 }
 
+#[rocket::delete("/<shortcode>")]
+pub async fn delete_clip(
+    shortcode: Shortcode,
+    db: &State<AppDatabase>,
+    cookies: &CookieJar<'_>,
+    password: ClipPassword,
+    edit_token: ClipEditToken,
+    _api_key: ApiKey,
+) -> Result<Status, ApiError> {
+    let unlock = UnlockToken::find(&shortcode, cookies);
+    let req = service::ask::DeleteClip {
+        shortcode,
+        password: password.0,
+        edit_token: edit_token.0,
+    };
+    match unlock {
+        Some(unlock) => action::delete_unlocked_clip(req, &unlock, db.get_pool()).await?,
+        None => action::delete_clip(req, db.get_pool()).await?,
+    }
+    Ok(Status::NoContent)
+}
+
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![new_clip, get_clip, update_clip, new_api_key]
+    rocket::routes![
+        new_clip,
+        get_clip,
+        get_attachment,
+        search_clips,
+        get_revisions,
+        get_revision,
+        get_views,
+        update_clip,
+        delete_clip,
+        new_api_key
+    ]
 }
 
 pub mod catcher {
+    use super::error::{ErrorBody, Rejection};
+    use crate::web::rate_limit::{RetryAfter, TooManyRequests};
+    use rocket::http::Status;
     use rocket::serde::json::Json;
     use rocket::Request;
     use rocket::{catch, catchers, Catcher};
+    use serde_json::json;
+
+    /// The body of the guard that rejected the request, or a generic one for `status`.
+    fn rejection(req: &Request, status: Status) -> ErrorBody {
+        let rejection = req.local_cache(Rejection::default);
+        rejection
+            .0
+            .clone()
+            .unwrap_or_else(|| ErrorBody::of_status(status))
+    }
 
     #[catch(default)]
-    fn default(req: &Request) -> Json<&'static str> {
-        eprintln!("General error: {:?}", req);
-        Json("something went wrong...")
+    fn default(status: Status, req: &Request) -> Json<ErrorBody> {
+        if status.class().is_server_error() {
+            eprintln!("Internal error: {:?}", req);
+        }
+        Json(rejection(req, status))
     }
 
-    #[catch(500)]
-    fn internal_error(req: &Request) -> Json<&'static str> {
-        eprintln!("Internal error: {:?}", req);
-        Json("internal server error")
+    #[catch(429)]
+    fn too_many_requests(req: &Request) -> TooManyRequests<Json<ErrorBody>> {
+        let retry_after = RetryAfter::of(req);
+        let mut body = rejection(req, Status::TooManyRequests);
+        if let Some(secs) = retry_after.0 {
+            body = body.with_details(json!({ "retry_after": secs }));
+        }
+        TooManyRequests(Json(body), retry_after)
     }
 
-    #[catch(404)]
-    fn not_found() -> Json<&'static str> {
-        Json("404")
+    pub fn catchers() -> Vec<Catcher> {
+        catchers![default, too_many_requests]
     }
 
-    #[catch(401)]
-    fn request_err() -> Json<&'static str> {
-        Json("request error")
+    #[cfg(test)]
+    pub mod test {
+        use crate::web::api::ClipEditToken;
+        use rocket::http::Status;
+        use rocket::local::blocking::Client;
+        use serde_json::{json, Value};
+
+        #[rocket::get("/edit")]
+        pub fn edit(_edit_token: ClipEditToken) -> Status {
+            Status::NoContent
+        }
+
+        #[rocket::get("/fail")]
+        pub fn fail() -> Status {
+            Status::InternalServerError
+        }
+
+        fn client() -> Client {
+            let rocket = rocket::build()
+                .mount("/api/clip", rocket::routes![edit, fail])
+                .register("/api/clip", super::catchers());
+            Client::tracked(rocket).expect("valid rocket")
+        }
+
+        fn get(client: &Client, uri: &str) -> (Status, Value) {
+            let response = client.get(uri).dispatch();
+            (
+                response.status(),
+                response.into_json().expect("JSON error body"),
+            )
+        }
+
+        #[test]
+        fn catchers_send_the_error_body() {
+            let client = client();
+            assert_eq!(
+                get(&client, "/api/clip/no/such/route"),
+                (
+                    Status::NotFound,
+                    json!({ "code": "not_found", "message": "not found", "details": null })
+                )
+            );
+            assert_eq!(
+                get(&client, "/api/clip/edit"),
+                (
+                    Status::Unauthorized,
+                    json!({ "code": "missing_edit_token", "message": "missing edit token", "details": null })
+                )
+            );
+            let (status, body) = get(&client, "/api/clip/fail");
+            assert_eq!(status, Status::InternalServerError);
+            assert_eq!(body["code"], "internal_server_error");
+        }
     }
+}
 
-    #[catch(400)]
-    fn missing_api_key() -> Json<&'static str> {
-        Json("missing API key")
+#[cfg(test)]
+pub mod test {
+    use super::{catcher, routes, API_KEY_HEADER};
+    use crate::data::test::new_db;
+    use crate::data::AppDatabase;
+    use crate::service::{action, ask};
+    use crate::test::async_runtime;
+    use crate::web::rate_limit::RateLimiter;
+    use crate::web::HitCounter;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::Client;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    /// Runs `test` against the API backed by a fresh database, with a key allowed to
+    /// read and write clips.
+    fn with_api<F, Fut>(test: F)
+    where
+        F: FnOnce(Client, String) -> Fut,
+        Fut: std::future::Future<Output = ()>,
+    {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool().clone();
+        let hit_counter =
+            HitCounter::new(pool.clone(), rt.handle().clone(), Duration::from_secs(3600));
+        rt.block_on(async move {
+            let req = ask::NewApiKey {
+                label: "test".to_owned(),
+                expires: None,
+                scopes: Default::default(),
+            };
+            let (key, _) = action::generate_api_key(req, &pool).await.unwrap();
+            let rocket = rocket::custom(crate::figment())
+                .manage::<AppDatabase>(db)
+                .manage(hit_counter)
+                .manage(RateLimiter::default())
+                .mount("/api/clip", routes())
+                .register("/api/clip", catcher::catchers());
+            let client = Client::tracked(rocket).await.expect("valid rocket");
+            test(client, key.to_base64()).await;
+        });
     }
 
-    pub fn catchers() -> Vec<Catcher> {
-        catchers![
-            not_found,
-            internal_error,
-            default,
-            request_err,
-            missing_api_key
-        ]
+    async fn post_clip(client: &Client, key: &str, body: &Value) -> (Status, Value) {
+        let response = client
+            .post("/api/clip")
+            .header(ContentType::JSON)
+            .header(Header::new(API_KEY_HEADER, key.to_owned()))
+            .body(body.to_string())
+            .dispatch()
+            .await;
+        let status = response.status();
+        (status, response.into_json().await.expect("JSON body"))
+    }
+
+    #[test]
+    fn zero_max_views_is_rejected() {
+        with_api(|client, key| async move {
+            let body = json!({
+                "content": "never readable",
+                "title": null,
+                "expires": null,
+                "password": null,
+                "max_views": 0,
+            });
+            let (status, error) = post_clip(&client, &key, &body).await;
+            assert_eq!(status, Status::BadRequest);
+            assert_eq!(error["code"], "invalid_max_views");
+        });
+    }
+
+    #[test]
+    fn search_sends_plain_text_snippets() {
+        with_api(|client, key| async move {
+            let body = json!({
+                "content": "if a < b && c > d { find(me) }",
+                "title": null,
+                "expires": null,
+                "password": null,
+            });
+            let (status, _) = post_clip(&client, &key, &body).await;
+            assert_eq!(status, Status::Ok);
+
+            let response = client
+                .get("/api/clip/search?q=find")
+                .header(Header::new(API_KEY_HEADER, key))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+            let results: Value = response.into_json().await.expect("JSON body");
+            let snippet = results[0]["snippet"].as_str().unwrap();
+            assert!(snippet.contains("a < b && c > d"));
+            let found = &results[0]["matches"][0];
+            let (start, end) = (
+                found["start"].as_u64().unwrap(),
+                found["end"].as_u64().unwrap(),
+            );
+            assert_eq!(&snippet[start as usize..end as usize], "find");
+        });
+    }
+
+    #[test]
+    fn content_over_the_limit_is_reported() {
+        use crate::domain::limits;
+
+        let max_bytes = limits::current().content_bytes();
+        with_api(|client, key| async move {
+            let clip = |content: String| {
+                json!({
+                    "content": content,
+                    "title": null,
+                    "expires": null,
+                    "password": null,
+                })
+            };
+
+            // Escaping doubles the size of quotes, the body is still read in full.
+            let (status, _) =
+                post_clip(&client, &key, &clip("\"".repeat(max_bytes as usize))).await;
+            assert_eq!(status, Status::Ok);
+
+            let too_long = clip("x".repeat(max_bytes as usize + 1));
+            let (status, error) = post_clip(&client, &key, &too_long).await;
+            assert_eq!(status, Status::BadRequest);
+            assert_eq!(error["code"], "content_too_long");
+            assert_eq!(error["details"]["max_bytes"], max_bytes);
+
+            // Bodies past the JSON limit are cut off before the content can be checked.
+            let cut_off = clip("\u{1}".repeat(max_bytes as usize + 64 * 1024));
+            let (status, error) = post_clip(&client, &key, &cut_off).await;
+            assert_eq!(status, Status::BadRequest);
+            assert_eq!(error["code"], "content_too_long");
+            assert_eq!(error["details"]["max_bytes"], max_bytes);
+        });
     }
 }
@@ -0,0 +1,98 @@
+//! Serves files attached to clips. Uploaded files are untrusted, so only raster images
+//! are shown inline and every response tells the browser not to sniff or run it.
+
+use crate::domain::clip::AttachmentData;
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use std::io::Cursor;
+
+pub struct AttachmentFile {
+    file: AttachmentData,
+    download: bool,
+}
+
+impl AttachmentFile {
+    /// Shows images in the browser and offers anything else as a download.
+    pub fn new(file: AttachmentData) -> Self {
+        Self {
+            file,
+            download: false,
+        }
+    }
+
+    /// Offers the file as a download whatever its type.
+    pub fn download(file: AttachmentData) -> Self {
+        Self {
+            file,
+            download: true,
+        }
+    }
+}
+
+/// `Content-Disposition` value with an ASCII file name for old clients and the
+/// exact name percent-encoded as UTF-8 for everyone else.
+fn content_disposition(name: &str, inline: bool) -> String {
+    let fallback: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded: String = name
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        if inline { "inline" } else { "attachment" },
+        fallback,
+        encoded
+    )
+}
+
+impl<'r> Responder<'r, 'static> for AttachmentFile {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let AttachmentData { attachment, data } = self.file;
+        let inline = attachment.is_image() && !self.download;
+        let content_type =
+            ContentType::parse_flexible(&attachment.mime).unwrap_or(ContentType::Binary);
+        Response::build()
+            .header(content_type)
+            .raw_header(
+                "Content-Disposition",
+                content_disposition(&attachment.name, inline),
+            )
+            .raw_header("X-Content-Type-Options", "nosniff")
+            .raw_header("Content-Security-Policy", "sandbox")
+            .sized_body(data.len(), Cursor::new(data))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::content_disposition;
+
+    #[test]
+    fn file_names_are_quoted_safely() {
+        assert_eq!(
+            content_disposition("report.pdf", false),
+            "attachment; filename=\"report.pdf\"; filename*=UTF-8''report.pdf"
+        );
+        assert_eq!(
+            content_disposition("a\"b;ü.png", true),
+            "inline; filename=\"a_b;_.png\"; filename*=UTF-8''a%22b%3B%C3%BC.png"
+        );
+    }
+}
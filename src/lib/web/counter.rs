@@ -1,111 +1,218 @@
 use crate::data::DatabasePool;
 use crate::domain::clip::field::Shortcode;
+use crate::domain::views::{AgentClass, ViewPeriod, ViewSource};
 use crate::service::{self, ServiceError};
-use crossbeam_channel::TryRecvError;
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use parking_lot::Mutex;
+use chrono::Utc;
+use rocket::fairing::AdHoc;
+use rocket::http::uri::Absolute;
+use rocket::request::{FromRequest, Outcome, Request};
 use std::collections::HashMap;
-use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::MissedTickBehavior;
 
-type HitStore = Arc<Mutex<HashMap<Shortcode, u32>>>;
+/// Lifetime hits per clip, and views per clip, hour and source.
+#[derive(Default)]
+struct HitStore {
+    hits: HashMap<Shortcode, u32>,
+    views: HashMap<(Shortcode, i64, ViewSource), u32>,
+}
+
+impl HitStore {
+    fn add(&mut self, shortcode: Shortcode, bucket: i64, source: ViewSource, count: u32) {
+        *self.hits.entry(shortcode.clone()).or_insert(0) += count;
+        *self.views.entry((shortcode, bucket, source)).or_insert(0) += count;
+    }
+
+    fn merge(&mut self, other: HitStore) {
+        for (shortcode, count) in other.hits {
+            *self.hits.entry(shortcode).or_insert(0) += count;
+        }
+        for (key, count) in other.views {
+            *self.views.entry(key).or_insert(0) += count;
+        }
+    }
 
-#[derive(Debug, thiserror::Error)]
-enum HitCounterError {
-    #[error("Service error: {0}")]
-    Service(#[from] ServiceError),
-    #[error("communication error: {0}")]
-    Channel(#[from] crossbeam_channel::SendError<HitCounterMsg>),
+    fn is_empty(&self) -> bool {
+        self.hits.is_empty() && self.views.is_empty()
+    }
 }
 
 enum HitCounterMsg {
-    Commit,
-    Hit(Shortcode, u32),
+    Hit(Shortcode, i64, ViewSource),
+    Flush(oneshot::Sender<()>),
 }
 
+/// Collects clip hits in memory and writes them to the database every `interval`.
 pub struct HitCounter {
-    tx: Sender<HitCounterMsg>,
+    tx: mpsc::UnboundedSender<HitCounterMsg>,
 }
 
 impl HitCounter {
-    fn commit_hits(
-        hits: HitStore,
-        handle: Handle,
-        pool: DatabasePool,
-    ) -> Result<(), HitCounterError> {
-        let hits = Arc::clone(&hits);
-        let hits: Vec<(Shortcode, u32)> = {
-            let mut hits = hits.lock();
-            let hits_vec = hits.iter().map(|(k, v)| (k.clone(), *v)).collect();
-            hits.clear();
-            hits_vec
-        };
-        handle.block_on(async move {
-            let transaction = service::action::begin_transaction(&pool).await?;
-            for (shortcode, hits) in hits {
-                if let Err(e) = service::action::increase_hits_count(&shortcode, hits, &pool).await
-                {
-                    eprintln!("error increasing hit count {}", e)
-                }
+    /// Writes every collected hit in one transaction. Hits are kept for the next
+    /// attempt if it fails.
+    async fn commit_hits(hits: &mut HitStore, pool: &DatabasePool) -> Result<(), ServiceError> {
+        if hits.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(hits);
+        let result = async {
+            let mut transaction = service::action::begin_transaction(pool).await?;
+            for (shortcode, count) in pending.hits.iter() {
+                service::action::increase_hits_count(shortcode, *count, &mut transaction).await?;
             }
-            Ok(service::action::end_transaction(transaction).await?)
-        })
-    }
-
-    fn process_msg(
-        msg: HitCounterMsg,
-        hits: HitStore,
-        handle: Handle,
-        pool: DatabasePool,
-    ) -> Result<(), HitCounterError> {
-        match msg {
-            HitCounterMsg::Commit => Self::commit_hits(hits, handle, pool)?,
-            HitCounterMsg::Hit(shortcode, count) => {
-                let mut hitcount = hits.lock();
-                let hitcount = hitcount.entry(shortcode).or_insert(0);
-                *hitcount += count;
+            for ((shortcode, bucket, source), count) in pending.views.iter() {
+                service::action::record_views(shortcode, *bucket, source, *count, &mut transaction)
+                    .await?;
             }
+            service::action::end_transaction(transaction).await
         }
-        Ok(())
+        .await;
+        if result.is_err() {
+            hits.merge(pending);
+        }
+        result
     }
 
-    pub fn new(pool: DatabasePool, handle: Handle) -> Self {
-        let (tx, rx) = unbounded();
-        let tx_clone = tx.clone();
-        let rx_clone = rx.clone();
-
-        let _ = std::thread::spawn(move || {
-            println!("HitCounter thread spawned");
-            let store: HitStore = Arc::new(Mutex::new(HashMap::new()));
-
-            loop {
-                match rx_clone.try_recv() {
-                    Ok(msg) => {
-                        if let Err(e) =
-                            Self::process_msg(msg, store.clone(), handle.clone(), pool.clone())
-                        {
-                            eprintln!("message processing error: {}", e);
-                        }
+    async fn run(
+        mut rx: mpsc::UnboundedReceiver<HitCounterMsg>,
+        pool: DatabasePool,
+        period: Duration,
+    ) {
+        let mut hits = HitStore::default();
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(HitCounterMsg::Hit(shortcode, bucket, source)) => {
+                        hits.add(shortcode, bucket, source, 1);
                     }
-                    Err(e) => match e {
-                        TryRecvError::Empty => {
-                            std::thread::sleep(Duration::from_secs(5));
-                            if let Err(e) = tx_clone.send(HitCounterMsg::Commit) {
-                                eprintln!("error sending commit message to hits channel")
-                            }
+                    Some(HitCounterMsg::Flush(done)) => {
+                        if let Err(e) = Self::commit_hits(&mut hits, &pool).await {
+                            eprintln!("error committing hits: {}", e);
                         }
-                        _ => break,
-                    },
+                        let _ = done.send(());
+                    }
+                    None => break,
+                },
+                _ = interval.tick() => {
+                    if let Err(e) = Self::commit_hits(&mut hits, &pool).await {
+                        eprintln!("error committing hits: {}", e);
+                    }
                 }
             }
-        });
+        }
+        if let Err(e) = Self::commit_hits(&mut hits, &pool).await {
+            eprintln!("error committing hits: {}", e);
+        }
+    }
+
+    pub fn new(pool: DatabasePool, handle: Handle, interval: Duration) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        handle.spawn(Self::run(rx, pool, interval));
         Self { tx }
     }
 
-    pub fn hit(&self, shortcode: Shortcode, count: u32) {
-        if let Err(e) = self.tx.send(HitCounterMsg::Hit(shortcode, count)) {
+    /// Counts one view of the clip, from `source`, in the current hour.
+    pub fn hit(&self, shortcode: Shortcode, source: ViewSource) {
+        let bucket = ViewPeriod::Hour.bucket(Utc::now().timestamp());
+        if let Err(e) = self.tx.send(HitCounterMsg::Hit(shortcode, bucket, source)) {
             eprintln!("hit counter: {}", e)
         }
     }
+
+    /// Writes the hits collected so far, waiting until they are committed.
+    pub async fn flush(&self) {
+        let (done, committed) = oneshot::channel();
+        if self.tx.send(HitCounterMsg::Flush(done)).is_ok() {
+            let _ = committed.await;
+        }
+    }
+
+    /// Flushes the managed `HitCounter` when Rocket shuts down so no hits are lost.
+    pub fn flush_on_shutdown() -> AdHoc {
+        AdHoc::on_shutdown("Flush hit counter", |rocket| {
+            Box::pin(async move {
+                if let Some(counter) = rocket.state::<HitCounter>() {
+                    counter.flush().await;
+                }
+            })
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ViewSource {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let referrer_host = req
+            .headers()
+            .get_one("Referer")
+            .and_then(|referer| Absolute::parse(referer).ok())
+            .and_then(|uri| uri.authority().map(|authority| authority.host().to_owned()))
+            .unwrap_or_default();
+        let agent = AgentClass::classify(req.headers().get_one("User-Agent"));
+        Outcome::Success(ViewSource {
+            referrer_host,
+            agent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::test::new_db;
+    use crate::service::ask;
+    use crate::test::async_runtime;
+
+    #[test]
+    fn flush_commits_pending_hits() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool().clone();
+        let counter = HitCounter::new(pool.clone(), rt.handle().clone(), Duration::from_secs(3600));
+
+        rt.block_on(async move {
+            let req = ask::NewClip {
+                content: crate::domain::clip::field::Content::new("counted").unwrap(),
+                title: Default::default(),
+                expires: Default::default(),
+                password: Default::default(),
+                max_views: Default::default(),
+                shortcode: None,
+                language: Default::default(),
+                format: Default::default(),
+                attachment: None,
+            };
+            let clip = service::action::new_clip(req, &pool).await.unwrap();
+            let source = ViewSource {
+                referrer_host: "wiki.example.com".to_owned(),
+                agent: AgentClass::Browser,
+            };
+            for _ in 0..3 {
+                counter.hit(clip.shortcode.clone(), source.clone());
+            }
+            counter.flush().await;
+
+            let edit_token = clip.edit_token.clone().unwrap();
+            let shortcode = clip.shortcode.clone();
+            let clip = service::action::get_clip(clip.shortcode.into(), &pool)
+                .await
+                .unwrap();
+            assert_eq!(clip.hits.into_inner(), 3);
+
+            let history =
+                service::action::get_view_history(&shortcode, ViewPeriod::Hour, &edit_token, &pool)
+                    .await
+                    .unwrap();
+            assert_eq!(history.buckets.len(), 1);
+            assert_eq!(history.buckets[0].views, 3);
+            assert_eq!(history.referrers[0].name, "wiki.example.com");
+            assert_eq!(history.agents[0].name, "browser");
+        });
+    }
 }
@@ -1,3 +1,4 @@
+use crate::domain::clip::{AttachmentData, SearchResult};
 use derive_more::Constructor;
 use serde::Serialize;
 
@@ -30,9 +31,36 @@ impl PageContext for Home {
     }
 }
 
-#[derive(Debug, Serialize, Constructor)]
+#[derive(Debug, Serialize)]
 pub struct ViewClip {
     pub clip: crate::Clip,
+    pub owner: bool,
+    pub lines: Vec<crate::web::highlight::Line>,
+    /// Whether to preview the attachment as an image.
+    pub image: bool,
+    /// The image itself as a `data:` URL, when the page has to carry it.
+    pub preview: Option<String>,
+}
+
+impl ViewClip {
+    pub fn new(clip: crate::Clip, owner: bool, preview: Option<AttachmentData>) -> Self {
+        use crate::domain::clip::field::Format;
+        let language = match clip.format {
+            Format::Plain => None,
+            Format::Markdown => Some("markdown"),
+            Format::Code => clip.language.as_deref(),
+        };
+        let lines = crate::web::highlight::highlight(clip.content.as_str(), language);
+        let preview = preview.map(data_url);
+        let image = has_image(&clip, owner, &preview);
+        Self {
+            clip,
+            owner,
+            lines,
+            image,
+            preview,
+        }
+    }
 }
 
 impl PageContext for ViewClip {
@@ -49,6 +77,236 @@ impl PageContext for ViewClip {
     }
 }
 
+/// Images are previewed unless loading them would use up a view the reader has not
+/// asked for. Owners read without using views, and a `preview` came with the view of
+/// the page.
+fn has_image(clip: &crate::Clip, owner: bool, preview: &Option<String>) -> bool {
+    let image = clip
+        .attachment
+        .as_ref()
+        .map(|attachment| attachment.is_image())
+        .unwrap_or(false);
+    image && (owner || preview.is_some() || !clip.max_views.is_limited())
+}
+
+fn data_url(file: AttachmentData) -> String {
+    use base64::Engine;
+    let data = base64::engine::general_purpose::STANDARD.encode(file.data);
+    format!("data:{};base64,{}", file.attachment.mime, data)
+}
+
+/// A Markdown clip rendered to sanitized HTML, `?format=source` shows a `ViewClip` instead.
+#[derive(Debug, Serialize)]
+pub struct ViewMarkdown {
+    pub clip: crate::Clip,
+    pub owner: bool,
+    pub html: String,
+    /// Tells the shared sidebar to link to the source view.
+    pub rendered: bool,
+    pub image: bool,
+    pub preview: Option<String>,
+}
+
+impl ViewMarkdown {
+    pub fn new(clip: crate::Clip, owner: bool, preview: Option<AttachmentData>) -> Self {
+        let html = crate::web::markdown::render(clip.content.as_str());
+        let preview = preview.map(data_url);
+        let image = has_image(&clip, owner, &preview);
+        Self {
+            clip,
+            owner,
+            html,
+            rendered: true,
+            image,
+            preview,
+        }
+    }
+}
+
+impl PageContext for ViewMarkdown {
+    fn title(&self) -> &str {
+        "View Clip"
+    }
+
+    fn template_path(&self) -> &str {
+        "clip_markdown"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Search {
+    pub query: String,
+    pub results: Vec<SearchHit>,
+}
+
+impl Search {
+    pub fn new(query: String, results: Vec<SearchResult>) -> Self {
+        let results = results
+            .into_iter()
+            .map(|result| SearchHit {
+                snippet: mark_matches(&result),
+                shortcode: result.shortcode,
+                title: result.title,
+            })
+            .collect();
+        Self { query, results }
+    }
+}
+
+/// A search result as listed on the page.
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub shortcode: crate::Shortcode,
+    pub title: crate::domain::clip::field::Title,
+    /// The snippet escaped as HTML, with the matched terms wrapped in `<mark>`.
+    pub snippet: String,
+}
+
+fn mark_matches(result: &SearchResult) -> String {
+    use handlebars::html_escape;
+    let snippet = result.snippet.as_str();
+    let mut html = String::with_capacity(snippet.len());
+    let mut at = 0;
+    for found in &result.matches {
+        if let (Some(before), Some(matched)) = (
+            snippet.get(at..found.start),
+            snippet.get(found.start..found.end),
+        ) {
+            html.push_str(&html_escape(before));
+            html.push_str("<mark>");
+            html.push_str(&html_escape(matched));
+            html.push_str("</mark>");
+            at = found.end;
+        }
+    }
+    html.push_str(&html_escape(snippet.get(at..).unwrap_or_default()));
+    html
+}
+
+impl PageContext for Search {
+    fn title(&self) -> &str {
+        "Search Clips"
+    }
+
+    fn template_path(&self) -> &str {
+        "search"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+#[derive(Debug, Serialize, Constructor)]
+pub struct Revisions {
+    pub shortcode: crate::Shortcode,
+    pub revisions: Vec<crate::domain::clip::Revision>,
+    /// Revision shown on the old side of the diff.
+    pub from: Option<u32>,
+    /// Revision shown on the new side of the diff, `None` for the current content.
+    pub to: Option<u32>,
+    pub diff: Vec<crate::web::diff::DiffLine>,
+}
+
+impl PageContext for Revisions {
+    fn title(&self) -> &str {
+        "Clip History"
+    }
+
+    fn template_path(&self) -> &str {
+        "revisions"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+/// One bar of the views chart, `percent` is relative to the busiest row.
+#[derive(Debug, Serialize)]
+pub struct ViewRow {
+    pub label: String,
+    pub views: u64,
+    pub percent: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Views {
+    pub shortcode: crate::Shortcode,
+    pub period: crate::domain::views::ViewPeriod,
+    pub total: u64,
+    pub buckets: Vec<ViewRow>,
+    pub referrers: Vec<ViewRow>,
+    pub agents: Vec<ViewRow>,
+}
+
+impl Views {
+    pub fn new(shortcode: crate::Shortcode, history: crate::domain::views::ViewHistory) -> Self {
+        use crate::domain::views::{ViewCount, ViewPeriod};
+
+        fn rows(counts: impl Iterator<Item = (String, u64)>) -> Vec<ViewRow> {
+            let counts: Vec<_> = counts.collect();
+            let busiest = counts
+                .iter()
+                .map(|(_, views)| *views)
+                .max()
+                .unwrap_or(0)
+                .max(1);
+            counts
+                .into_iter()
+                .map(|(label, views)| ViewRow {
+                    label,
+                    views,
+                    percent: views * 100 / busiest,
+                })
+                .collect()
+        }
+        let named = |counts: Vec<ViewCount>| {
+            rows(counts.into_iter().map(|count| {
+                let name = if count.name.is_empty() {
+                    "(direct)".to_owned()
+                } else {
+                    count.name
+                };
+                (name, count.views)
+            }))
+        };
+        let format = match history.period {
+            ViewPeriod::Hour => "%Y-%m-%d %H:00",
+            ViewPeriod::Day => "%Y-%m-%d",
+        };
+        Self {
+            shortcode,
+            period: history.period,
+            total: history.buckets.iter().map(|bucket| bucket.views).sum(),
+            buckets: rows(history.buckets.into_iter().map(|bucket| {
+                let label = bucket.start.into_inner().format(format).to_string();
+                (label, bucket.views)
+            })),
+            referrers: named(history.referrers),
+            agents: named(history.agents),
+        }
+    }
+}
+
+impl PageContext for Views {
+    fn title(&self) -> &str {
+        "Clip Views"
+    }
+
+    fn template_path(&self) -> &str {
+        "views"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
 #[derive(Debug, Serialize, Constructor)]
 pub struct PassRequired {
     shortcode: crate::Shortcode,
@@ -67,3 +325,25 @@ impl PageContext for PassRequired {
         "base"
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Search;
+    use crate::domain::clip::{SearchResult, SnippetMatch};
+
+    #[test]
+    fn search_snippets_are_escaped_and_marked() {
+        let result = SearchResult {
+            shortcode: "abc".into(),
+            title: Default::default(),
+            snippet: "if a < b { find(me) }".to_owned(),
+            matches: vec![SnippetMatch { start: 11, end: 15 }],
+            rank: 0.0,
+        };
+        let search = Search::new("find".to_owned(), vec![result]);
+        assert_eq!(
+            search.results[0].snippet,
+            "if a &lt; b { <mark>find</mark>(me) }"
+        );
+    }
+}
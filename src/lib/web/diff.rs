@@ -0,0 +1,53 @@
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Hunk,
+    Added,
+    Removed,
+    Context,
+}
+
+/// One line of a unified diff, kept structured so templates can style and escape it.
+#[derive(Debug, Serialize)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    pub text: String,
+}
+
+pub fn unified_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = vec![];
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        lines.push(DiffLine {
+            kind: LineKind::Hunk,
+            text: hunk.header().to_string(),
+        });
+        for change in hunk.iter_changes() {
+            let (kind, sign) = match change.tag() {
+                ChangeTag::Insert => (LineKind::Added, '+'),
+                ChangeTag::Delete => (LineKind::Removed, '-'),
+                ChangeTag::Equal => (LineKind::Context, ' '),
+            };
+            lines.push(DiffLine {
+                kind,
+                text: format!("{}{}", sign, change.value().trim_end_matches('\n')),
+            });
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn marks_changed_lines() {
+        let lines = unified_diff("a\nb\nc\n", "a\nB\nc\n");
+        let text = lines.iter().map(|l| l.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(text, vec!["@@ -1,3 +1,3 @@", " a", "-b", "+B", " c"]);
+    }
+}
@@ -0,0 +1,101 @@
+//! Serves raw clip content compressed when the client accepts it.
+//!
+//! Content stored compressed goes out as is to clients accepting zstd, and is gzipped
+//! for clients that only accept gzip. Small content is always sent uncompressed.
+
+use crate::Clip;
+use flate2::write::GzEncoder;
+use rocket::http::{ContentType, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use std::io::{Cursor, Write};
+
+pub struct RawContent {
+    content: String,
+    zstd: Option<Vec<u8>>,
+}
+
+impl RawContent {
+    pub fn new(clip: Clip) -> Self {
+        Self {
+            content: clip.content.into_inner(),
+            zstd: clip.content_zstd,
+        }
+    }
+}
+
+impl From<String> for RawContent {
+    fn from(content: String) -> Self {
+        Self {
+            content,
+            zstd: None,
+        }
+    }
+}
+
+/// Whether an `Accept-Encoding` value allows `coding`, honouring `q=0` and `*`.
+fn accepts(accept_encoding: &str, coding: &str) -> bool {
+    let mut wildcard = false;
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        let quality = parts
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|quality| quality.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if name.eq_ignore_ascii_case(coding) {
+            return quality > 0.0;
+        }
+        if name == "*" {
+            wildcard = quality > 0.0;
+        }
+    }
+    wildcard
+}
+
+fn gzip(content: &str) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], flate2::Compression::fast());
+    encoder.write_all(content.as_bytes())?;
+    encoder.finish()
+}
+
+impl<'r> Responder<'r, 'static> for RawContent {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let accept_encoding = req
+            .headers()
+            .get("Accept-Encoding")
+            .collect::<Vec<_>>()
+            .join(",");
+        let (encoding, body) = match self.zstd {
+            Some(zstd) if accepts(&accept_encoding, "zstd") => (Some("zstd"), zstd),
+            Some(_) if accepts(&accept_encoding, "gzip") => (
+                Some("gzip"),
+                gzip(&self.content).map_err(|_| Status::InternalServerError)?,
+            ),
+            _ => (None, self.content.into_bytes()),
+        };
+        let mut response = Response::build();
+        response
+            .header(ContentType::Plain)
+            .raw_header("Vary", "Accept-Encoding");
+        if let Some(encoding) = encoding {
+            response.raw_header("Content-Encoding", encoding);
+        }
+        response.sized_body(body.len(), Cursor::new(body)).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::accepts;
+
+    #[test]
+    fn accept_encoding_is_parsed() {
+        assert!(accepts("gzip, deflate, br, zstd", "zstd"));
+        assert!(accepts("GZIP;q=0.5", "gzip"));
+        assert!(!accepts("gzip, zstd;q=0", "zstd"));
+        assert!(accepts("*", "zstd"));
+        assert!(!accepts("*, zstd;q=0.0", "zstd"));
+        assert!(!accepts("", "gzip"));
+    }
+}
@@ -1,14 +1,56 @@
-use crate::domain::clip::field;
+use crate::domain::clip::{field, ClipError};
+use crate::service::ask;
+use crate::Shortcode;
 use rocket::form::FromForm;
 use serde::Serialize;
 
 #[derive(Debug, Serialize, FromForm)]
 pub struct NewClip {
-    pub content: field::Content,
+    /// May be left empty when a file is attached, the file name is used instead.
+    pub content: Option<String>,
     pub title: field::Title,
     pub expires: field::Expires,
     pub password: field::Password,
+    pub max_views: field::MaxViews,
+    /// Requested shortcode, empty for a random one.
+    pub shortcode: Option<String>,
+    pub language: field::Language,
+    pub format: field::Format,
+    #[serde(skip)]
+    pub attachment: field::Upload,
 }
+
+impl NewClip {
+    pub fn into_request(self) -> Result<ask::NewClip, ClipError> {
+        let attachment = self.attachment.into_inner();
+        let content = self
+            .content
+            .as_deref()
+            .filter(|content| !content.trim().is_empty());
+        let content = match (content, &attachment) {
+            (Some(content), _) => field::Content::new(content)?,
+            (None, Some(file)) => field::Content::new(&file.attachment.name)?,
+            (None, None) => return Err(ClipError::EmptyContent),
+        };
+        Ok(ask::NewClip {
+            content,
+            title: self.title,
+            expires: self.expires,
+            password: self.password,
+            max_views: self.max_views,
+            shortcode: self
+                .shortcode
+                .as_deref()
+                .map(str::trim)
+                .filter(|shortcode| !shortcode.is_empty())
+                .map(Shortcode::from),
+            language: self.language,
+            format: self.format,
+            attachment,
+        })
+    }
+}
+
 #[derive(Debug, Serialize, FromForm)]
 pub struct GetPasswordProtectedClip {
     pub password: field::Password,
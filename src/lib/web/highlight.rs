@@ -0,0 +1,81 @@
+//! Server side syntax highlighting of clip content, one HTML fragment per line so the
+//! clip page can number lines and link to them.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Content larger than this is shown as plain text, highlighting it would take too long.
+const MAX_HIGHLIGHTED_BYTES: usize = 256 * 1024;
+
+static SYNTAXES: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+static THEME: Lazy<Theme> = Lazy::new(|| {
+    let mut themes = ThemeSet::load_defaults();
+    themes
+        .themes
+        .remove("InspiredGitHub")
+        .expect("default themes should include InspiredGitHub")
+});
+
+#[derive(Debug, Serialize)]
+pub struct Line {
+    pub number: usize,
+    /// Escaped line content wrapped in styled `<span>`s.
+    pub html: String,
+}
+
+fn syntax_for(language: Option<&str>, content: &str) -> &'static SyntaxReference {
+    let plain = SYNTAXES.find_syntax_plain_text();
+    if content.len() > MAX_HIGHLIGHTED_BYTES {
+        return plain;
+    }
+    language
+        .and_then(|language| SYNTAXES.find_syntax_by_token(language))
+        .unwrap_or(plain)
+}
+
+/// Highlights `content` as `language`, falling back to plain text for unknown languages.
+pub fn highlight(content: &str, language: Option<&str>) -> Vec<Line> {
+    let syntax = syntax_for(language, content);
+    let mut highlighter = HighlightLines::new(syntax, &THEME);
+    LinesWithEndings::from(content)
+        .enumerate()
+        .map(|(index, line)| {
+            let html = highlighter
+                .highlight_line(line, &SYNTAXES)
+                .and_then(|regions| {
+                    styled_line_to_highlighted_html(&regions, IncludeBackground::No)
+                })
+                .unwrap_or_else(|_| handlebars::html_escape(line));
+            Line {
+                number: index + 1,
+                html: html.replace(['\r', '\n'], ""),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::highlight;
+
+    #[test]
+    fn lines_are_numbered_and_escaped() {
+        let lines = highlight("fn main() {}\n<b>\n", Some("rust"));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].number, 2);
+        assert!(lines[0].html.contains("<span"));
+        assert!(lines[1].html.contains("&lt;"));
+        assert!(!lines[1].html.contains("<b>"));
+    }
+
+    #[test]
+    fn unknown_language_is_plain_text() {
+        let lines = highlight("<script>", Some("no-such-language"));
+        assert!(lines[0].html.contains("&lt;script&gt;"));
+    }
+}
@@ -1,12 +1,18 @@
 use crate::data::AppDatabase;
+use crate::domain::clip::field::Format;
+use crate::domain::clip::AttachmentData;
+use crate::domain::views::{ViewPeriod, ViewSource};
 use crate::service;
 use crate::service::action;
+use crate::web::attachment::AttachmentFile;
 use crate::web::counter::HitCounter;
-use crate::web::{ctx, form, render::Renderer, PageError, PASSWORD_COOKIE};
+use crate::web::encoding::RawContent;
+use crate::web::rate_limit::ClientRateLimit;
+use crate::web::{ctx, diff, form, owner, render::Renderer, PageError, UnlockToken};
 use crate::{ServiceError, Shortcode};
 use rocket::form::{Contextual, Form};
 
-use rocket::http::{Cookie, CookieJar, Status};
+use rocket::http::{CookieJar, Status};
 use rocket::response::content::RawHtml;
 use rocket::response::{status, Redirect};
 use rocket::{uri, State};
@@ -19,20 +25,44 @@ fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
 
 #[rocket::post("/", data = "<form>")]
 async fn post_clip(
+    cookies: &CookieJar<'_>,
     form: Form<Contextual<'_, form::NewClip>>,
     database: &State<AppDatabase>,
     renderer: &State<Renderer<'_>>,
+    _limit: ClientRateLimit,
 ) -> Result<Redirect, (Status, RawHtml<String>)> {
     let form = form.into_inner();
     if let Some(value) = form.value {
-        let req = service::ask::NewClip {
-            content: value.content,
-            title: value.title,
-            expires: value.expires,
-            password: value.password,
+        let created = match value.into_request() {
+            Ok(req) => action::new_clip(req, database.get_pool()).await,
+            Err(e) => Err(e.into()),
         };
-        match action::new_clip(req, database.get_pool()).await {
-            Ok(clip) => Ok(Redirect::to(uri!(get_clip(shortcode = clip.shortcode)))),
+        match created {
+            Ok(clip) => {
+                if let Some(edit_token) = &clip.edit_token {
+                    owner::remember(&clip.shortcode, edit_token, cookies);
+                }
+                Ok(Redirect::to(uri!(get_clip(
+                    shortcode = clip.shortcode,
+                    format = _
+                ))))
+            }
+            Err(ServiceError::Clip(e)) => Err((
+                Status::BadRequest,
+                RawHtml(renderer.render_with_data(
+                    ctx::Home::default(),
+                    ("clip", &form.context),
+                    &[e.to_string().as_str()],
+                )),
+            )),
+            Err(ServiceError::Conflict(msg)) => Err((
+                Status::Conflict,
+                RawHtml(renderer.render_with_data(
+                    ctx::Home::default(),
+                    ("clip", &form.context),
+                    &[msg.as_str()],
+                )),
+            )),
             Err(e) => {
                 eprintln!("internal error: {}", e);
                 Err((
@@ -50,14 +80,14 @@ async fn post_clip(
             .errors()
             .map(|err| {
                 use rocket::form::error::ErrorKind;
-                if let ErrorKind::Validation(msg) = &err.kind {
-                    msg.as_ref()
-                } else {
-                    eprintln!("unhandled error: {}", err);
-                    "An server error occurred. Please try again"
+                match (&err.kind, &err.name) {
+                    (ErrorKind::Validation(msg), _) => msg.to_string(),
+                    (kind, Some(name)) => format!("{}: {}", name, kind),
+                    (kind, None) => kind.to_string(),
                 }
             })
             .collect::<Vec<_>>();
+        let errors = errors.iter().map(String::as_str).collect::<Vec<_>>();
         Err((
             Status::BadRequest,
             RawHtml(renderer.render_with_data(
@@ -69,29 +99,43 @@ async fn post_clip(
     }
 }
 
+/// Renders Markdown clips as HTML unless `format` asks for the source.
+fn render_clip(
+    (clip, preview): (crate::Clip, Option<AttachmentData>),
+    owner: bool,
+    format: Option<&str>,
+    renderer: &Renderer,
+) -> String {
+    if clip.format == Format::Markdown && format != Some("source") {
+        renderer.render(ctx::ViewMarkdown::new(clip, owner, preview), &[])
+    } else {
+        renderer.render(ctx::ViewClip::new(clip, owner, preview), &[])
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 #[rocket::post("/clip/<shortcode>", data = "<form>")]
 async fn post_clip_with_password(
     cookies: &CookieJar<'_>,
     form: Form<Contextual<'_, form::GetPasswordProtectedClip>>,
     shortcode: Shortcode,
     hit_counter: &State<HitCounter>,
+    source: ViewSource,
     database: &State<AppDatabase>,
     renderer: &State<Renderer<'_>>,
+    _limit: ClientRateLimit,
 ) -> Result<RawHtml<String>, PageError> {
     if let Some(form) = &form.value {
         let req = service::ask::GetClip {
             shortcode: shortcode.clone(),
             password: form.password.clone(),
         };
-        match action::get_clip(req, database.get_pool()).await {
-            Ok(clip) => {
-                hit_counter.hit(shortcode.clone(), 1);
-                let context = ctx::ViewClip::new(clip);
-                cookies.add(Cookie::new(
-                    PASSWORD_COOKIE,
-                    form.password.clone().into_inner().unwrap_or_default(),
-                ));
-                Ok(RawHtml(renderer.render(context, &[])))
+        match action::get_clip_page(req, None, database.get_pool()).await {
+            Ok(page) => {
+                hit_counter.hit(shortcode.clone(), source);
+                let is_owner = owner::edit_token(&shortcode, cookies).is_some();
+                UnlockToken::issue(&page.0, cookies);
+                Ok(RawHtml(render_clip(page, is_owner, None, renderer)))
             }
             Err(e) => match e {
                 ServiceError::PermissionError(e) => {
@@ -111,12 +155,17 @@ async fn post_clip_with_password(
     }
 }
 
-#[rocket::get("/clip/<shortcode>")]
+#[allow(clippy::too_many_arguments)]
+#[rocket::get("/clip/<shortcode>?<format>")]
 async fn get_clip(
+    cookies: &CookieJar<'_>,
     shortcode: Shortcode,
+    format: Option<&str>,
     database: &State<AppDatabase>,
     hit_counter: &State<HitCounter>,
+    source: ViewSource,
     renderer: &State<Renderer<'_>>,
+    _limit: ClientRateLimit,
 ) -> Result<status::Custom<RawHtml<String>>, PageError> {
     fn render_with_status<T: ctx::PageContext + serde::Serialize + std::fmt::Debug>(
         status: Status,
@@ -129,11 +178,26 @@ async fn get_clip(
         ))
     }
 
-    match action::get_clip(shortcode.clone().into(), database.get_pool()).await {
-        Ok(clip) => {
-            hit_counter.hit(shortcode.clone(), 1);
-            let context = ctx::ViewClip::new(clip);
-            render_with_status(Status::Ok, context, renderer)
+    let pool = database.get_pool();
+    let owned = match owner::edit_token(&shortcode, cookies) {
+        Some(edit_token) => action::get_owned_clip(shortcode.clone(), &edit_token, pool)
+            .await
+            .ok(),
+        None => None,
+    };
+    let is_owner = owned.is_some();
+    let page = match owned {
+        Some(clip) => Ok((clip, None)),
+        None => {
+            let unlock = UnlockToken::find(&shortcode, cookies);
+            action::get_clip_page(shortcode.clone().into(), unlock.as_ref(), pool).await
+        }
+    };
+    match page {
+        Ok(page) => {
+            hit_counter.hit(shortcode.clone(), source);
+            let html = render_clip(page, is_owner, format, renderer);
+            Ok(status::Custom(Status::Ok, RawHtml(html)))
         }
         Err(e) => match e {
             ServiceError::PermissionError(_) => {
@@ -146,30 +210,204 @@ async fn get_clip(
     }
 }
 
+#[rocket::post("/clip/<shortcode>/delete")]
+async fn delete_clip(
+    cookies: &CookieJar<'_>,
+    shortcode: Shortcode,
+    database: &State<AppDatabase>,
+    _limit: ClientRateLimit,
+) -> Result<Redirect, PageError> {
+    let edit_token = match owner::edit_token(&shortcode, cookies) {
+        Some(edit_token) => edit_token,
+        None => {
+            return Err(PageError::Forbidden(
+                "Only the owner can delete this clip".to_owned(),
+            ))
+        }
+    };
+    let req = service::ask::DeleteClip {
+        shortcode: shortcode.clone(),
+        password: Default::default(),
+        edit_token,
+    };
+    let deleted = match UnlockToken::find(&shortcode, cookies) {
+        Some(unlock) => action::delete_unlocked_clip(req, &unlock, database.get_pool()).await,
+        None => action::delete_clip(req, database.get_pool()).await,
+    };
+    match deleted {
+        Ok(()) => {
+            owner::forget(&shortcode, cookies);
+            Ok(Redirect::to(uri!(home)))
+        }
+        Err(e) => match e {
+            ServiceError::PermissionError(msg) => Err(PageError::Forbidden(msg)),
+            ServiceError::NotFound => Err(PageError::NotFound("Clip not found".to_owned())),
+            _ => Err(PageError::Internal("Server error".to_owned())),
+        },
+    }
+}
+
+#[rocket::get("/clip/<shortcode>/revisions?<from>&<to>")]
+async fn get_revisions(
+    cookies: &CookieJar<'_>,
+    shortcode: Shortcode,
+    from: Option<u32>,
+    to: Option<u32>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+    _limit: ClientRateLimit,
+) -> Result<RawHtml<String>, PageError> {
+    let edit_token = match owner::edit_token(&shortcode, cookies) {
+        Some(edit_token) => edit_token,
+        None => {
+            return Err(PageError::Forbidden(
+                "Only the owner can view the history of this clip".to_owned(),
+            ))
+        }
+    };
+    let pool = database.get_pool();
+    let context = async {
+        let clip = action::get_owned_clip(shortcode.clone(), &edit_token, pool).await?;
+        let revisions = action::get_revisions(&shortcode, &edit_token, pool).await?;
+        let content_of = |revision: Option<u32>| match revision {
+            Some(n) => revisions
+                .iter()
+                .find(|r| r.revision == n)
+                .map(|r| r.content.as_str())
+                .ok_or(ServiceError::NotFound),
+            None => Ok(clip.content.as_str()),
+        };
+        let from = from.or_else(|| revisions.first().map(|r| r.revision));
+        let diff = diff::unified_diff(content_of(from)?, content_of(to)?);
+        Ok::<_, ServiceError>(ctx::Revisions::new(
+            shortcode.clone(),
+            revisions,
+            from,
+            to,
+            diff,
+        ))
+    };
+    match context.await {
+        Ok(context) => Ok(RawHtml(renderer.render(context, &[]))),
+        Err(e) => match e {
+            ServiceError::PermissionError(msg) => Err(PageError::Forbidden(msg)),
+            ServiceError::NotFound => Err(PageError::NotFound("Revision not found".to_owned())),
+            _ => Err(PageError::Internal("Server error".to_owned())),
+        },
+    }
+}
+
+#[rocket::get("/clip/<shortcode>/views?<period>")]
+async fn get_views(
+    cookies: &CookieJar<'_>,
+    shortcode: Shortcode,
+    period: Option<ViewPeriod>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+    _limit: ClientRateLimit,
+) -> Result<RawHtml<String>, PageError> {
+    let edit_token = match owner::edit_token(&shortcode, cookies) {
+        Some(edit_token) => edit_token,
+        None => {
+            return Err(PageError::Forbidden(
+                "Only the owner can view the statistics of this clip".to_owned(),
+            ))
+        }
+    };
+    let period = period.unwrap_or_default();
+    match action::get_view_history(&shortcode, period, &edit_token, database.get_pool()).await {
+        Ok(history) => {
+            let context = ctx::Views::new(shortcode, history);
+            Ok(RawHtml(renderer.render(context, &[])))
+        }
+        Err(e) => match e {
+            ServiceError::PermissionError(msg) => Err(PageError::Forbidden(msg)),
+            ServiceError::NotFound => Err(PageError::NotFound("Clip not found".to_owned())),
+            _ => Err(PageError::Internal("Server error".to_owned())),
+        },
+    }
+}
+
+#[rocket::get("/search?<q>")]
+async fn search(
+    q: Option<String>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+    _limit: ClientRateLimit,
+) -> Result<RawHtml<String>, PageError> {
+    let query = q.unwrap_or_default();
+    let req = service::ask::SearchClips {
+        query: query.clone(),
+        limit: None,
+    };
+    match action::search_clips(req, database.get_pool()).await {
+        Ok(results) => Ok(RawHtml(
+            renderer.render(ctx::Search::new(query, results), &[]),
+        )),
+        Err(e) => {
+            eprintln!("search error: {}", e);
+            Err(PageError::Internal("Server error".to_owned()))
+        }
+    }
+}
+
 #[rocket::get("/clip/raw/<shortcode>")]
 async fn get_raw_clip(
     cookies: &CookieJar<'_>,
     shortcode: Shortcode,
     hit_counter: &State<HitCounter>,
+    source: ViewSource,
     database: &State<AppDatabase>,
-) -> Result<status::Custom<String>, Status> {
-    use crate::domain::clip::field::Password;
-    let req = service::ask::GetClip {
-        shortcode: shortcode.clone(),
-        password: cookies
-            .get(PASSWORD_COOKIE)
-            .map(|cookie| cookie.value())
-            .map(|raw_pswd| Password::new(raw_pswd.to_string()).ok())
-            .flatten()
-            .unwrap_or_else(Password::default),
+    _limit: ClientRateLimit,
+) -> Result<status::Custom<RawContent>, Status> {
+    let clip = match UnlockToken::find(&shortcode, cookies) {
+        Some(unlock) => {
+            action::get_unlocked_clip(shortcode.clone().into(), &unlock, database.get_pool()).await
+        }
+        None => action::get_clip(shortcode.clone().into(), database.get_pool()).await,
     };
-    match action::get_clip(req, database.get_pool()).await {
+    match clip {
         Ok(clip) => {
-            hit_counter.hit(shortcode.clone(), 1);
-            Ok(status::Custom(Status::Ok, clip.content.into_inner()))
+            hit_counter.hit(shortcode.clone(), source);
+            Ok(status::Custom(Status::Ok, RawContent::new(clip)))
         }
         Err(e) => match e {
-            ServiceError::PermissionError(msg) => Ok(status::Custom(Status::Unauthorized, msg)),
+            ServiceError::PermissionError(msg) => {
+                Ok(status::Custom(Status::Unauthorized, msg.into()))
+            }
+            ServiceError::NotFound => Err(Status::NotFound),
+            _ => Err(Status::InternalServerError),
+        },
+    }
+}
+
+#[rocket::get("/clip/raw/<shortcode>/attachment?<download>")]
+async fn get_attachment(
+    cookies: &CookieJar<'_>,
+    shortcode: Shortcode,
+    download: Option<bool>,
+    database: &State<AppDatabase>,
+    _limit: ClientRateLimit,
+) -> Result<AttachmentFile, Status> {
+    let pool = database.get_pool();
+    let owned = match owner::edit_token(&shortcode, cookies) {
+        Some(edit_token) => action::get_owned_attachment(shortcode.clone(), &edit_token, pool)
+            .await
+            .ok(),
+        None => None,
+    };
+    let file = match owned {
+        Some(file) => Ok(file),
+        None => match UnlockToken::find(&shortcode, cookies) {
+            Some(unlock) => action::get_unlocked_attachment(shortcode.into(), &unlock, pool).await,
+            None => action::get_attachment(shortcode.into(), pool).await,
+        },
+    };
+    match file {
+        Ok(file) if download.unwrap_or(false) => Ok(AttachmentFile::download(file)),
+        Ok(file) => Ok(AttachmentFile::new(file)),
+        Err(e) => match e {
+            ServiceError::PermissionError(_) => Err(Status::Unauthorized),
             ServiceError::NotFound => Err(Status::NotFound),
             _ => Err(Status::InternalServerError),
         },
@@ -182,11 +420,17 @@ pub fn routes() -> Vec<rocket::Route> {
         get_clip,
         post_clip,
         post_clip_with_password,
-        get_raw_clip
+        delete_clip,
+        get_revisions,
+        get_views,
+        search,
+        get_raw_clip,
+        get_attachment
     ]
 }
 
 pub mod catcher {
+    use crate::web::rate_limit::{RetryAfter, TooManyRequests};
     use rocket::Request;
     use rocket::{catch, catchers, Catcher};
 
@@ -208,7 +452,104 @@ pub mod catcher {
         "internal server error"
     }
 
+    #[catch(429)]
+    fn too_many_requests(req: &Request) -> TooManyRequests<&'static str> {
+        TooManyRequests("too many requests, slow down", RetryAfter::of(req))
+    }
+
     pub fn catchers() -> Vec<Catcher> {
-        catchers![not_found, internal_error, default]
+        catchers![not_found, internal_error, default, too_many_requests]
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::routes;
+    use crate::data::test::new_db;
+    use crate::data::AppDatabase;
+    use crate::domain::clip::field::{Content, MaxViews};
+    use crate::domain::clip::{Attachment, AttachmentData};
+    use crate::service::{action, ask};
+    use crate::test::async_runtime;
+    use crate::web::rate_limit::RateLimiter;
+    use crate::web::render::Renderer;
+    use crate::web::HitCounter;
+    use crate::Shortcode;
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+    use std::time::Duration;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn image_clip(max_views: u32) -> ask::NewClip {
+        ask::NewClip {
+            content: Content::new("look at this").unwrap(),
+            title: Default::default(),
+            expires: Default::default(),
+            password: Default::default(),
+            max_views: MaxViews::new(Some(max_views)),
+            shortcode: None,
+            language: Default::default(),
+            format: Default::default(),
+            attachment: Some(AttachmentData {
+                attachment: Attachment {
+                    name: "shot.png".to_owned(),
+                    mime: "image/png".to_owned(),
+                    size: PNG.len() as u64,
+                },
+                data: PNG.to_vec(),
+            }),
+        }
+    }
+
+    async fn page(client: &Client, shortcode: &Shortcode) -> (Status, String) {
+        let response = client
+            .get(format!("/clip/{}", shortcode.as_str()))
+            .dispatch()
+            .await;
+        (response.status(), response.into_string().await.unwrap())
+    }
+
+    #[test]
+    fn view_limited_image_comes_with_the_page() {
+        use base64::Engine;
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool().clone();
+        let hit_counter =
+            HitCounter::new(pool.clone(), rt.handle().clone(), Duration::from_secs(3600));
+        let preview = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(PNG)
+        );
+
+        rt.block_on(async move {
+            let rocket = rocket::build()
+                .manage::<AppDatabase>(db)
+                .manage(Renderer::new("templates/".into()))
+                .manage(hit_counter)
+                .manage(RateLimiter::default())
+                .mount("/", routes());
+            let client = Client::tracked(rocket).await.expect("valid rocket");
+
+            // The page is the only view, it deletes the clip and still shows the image.
+            let clip = action::new_clip(image_clip(1), &pool).await.unwrap();
+            let (status, html) = page(&client, &clip.shortcode).await;
+            assert_eq!(status, Status::Ok);
+            assert!(html.contains(&preview.replace('=', "&#x3D;")));
+            let (status, _) = page(&client, &clip.shortcode).await;
+            assert_ne!(status, Status::Ok);
+
+            // Showing the image does not use up a view of its own.
+            let clip = action::new_clip(image_clip(2), &pool).await.unwrap();
+            for _ in 0..2 {
+                let (status, html) = page(&client, &clip.shortcode).await;
+                assert_eq!(status, Status::Ok);
+                assert!(html.contains("data:image/png;base64,"));
+            }
+            let (status, _) = page(&client, &clip.shortcode).await;
+            assert_ne!(status, Status::Ok);
+        });
     }
 }
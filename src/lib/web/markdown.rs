@@ -0,0 +1,85 @@
+//! Markdown clips rendered to HTML. Everything the renderer produces goes through an
+//! allow-list sanitizer, clip authors must not be able to run script on the page.
+
+use pulldown_cmark::{html, Options, Parser};
+
+/// Renders `content` as CommonMark with tables, strikethrough, task lists and
+/// footnotes, then strips anything that could run script or load remote content
+/// other than links and images.
+pub fn render(content: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let mut unsafe_html = String::with_capacity(content.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, Parser::new_ext(content, options));
+    ammonia::Builder::default()
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        .add_tags(["input"])
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+
+    #[test]
+    fn renders_common_markdown() {
+        let html = render("# Runbook\n\n* **restart** the `api`\n\n| a |\n|---|\n| 1 |");
+        assert!(html.contains("<h1>Runbook</h1>"));
+        assert!(html.contains("<strong>restart</strong>"));
+        assert!(html.contains("<code>api</code>"));
+        assert!(html.contains("<td>1</td>"));
+    }
+
+    #[test]
+    fn strips_xss_payloads() {
+        let payloads = [
+            "<script>alert(1)</script>",
+            "<img src=x onerror=alert(1)>",
+            "[click](javascript:alert(1))",
+            "[click](JaVaScRiPt:alert(1))",
+            "![x](javascript:alert(1))",
+            "<a href=\"javascript:alert(1)\">x</a>",
+            "<iframe src=\"https://evil.example\"></iframe>",
+            "<svg onload=alert(1)>",
+            "<div style=\"background:url(javascript:alert(1))\">x</div>",
+            "<object data=\"data:text/html,<script>alert(1)</script>\"></object>",
+            "[x](data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==)",
+            "<form action=\"https://evil.example\"><input name=q></form>",
+            "<meta http-equiv=\"refresh\" content=\"0;url=https://evil.example\">",
+        ];
+        for payload in payloads {
+            let html = render(payload).to_ascii_lowercase();
+            for needle in [
+                "<script",
+                "onerror",
+                "onload",
+                "javascript:",
+                "<iframe",
+                "<svg",
+                "style=",
+                "<object",
+                "data:",
+                "<form",
+                "<meta",
+            ] {
+                assert!(
+                    !html.contains(needle),
+                    "{:?} rendered as {:?}",
+                    payload,
+                    html
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn links_do_not_leak_the_opener() {
+        let html = render("[docs](https://example.com)");
+        assert!(html.contains("href=\"https://example.com\""));
+        assert!(html.contains("rel=\"noopener noreferrer nofollow\""));
+    }
+}
@@ -1,15 +1,22 @@
 use handlebars::RenderError;
 
 pub mod api;
+pub mod attachment;
 pub mod counter;
 pub mod ctx;
+pub mod diff;
+pub mod encoding;
 pub mod form;
+pub mod highlight;
 pub mod http;
+pub mod markdown;
+pub mod owner;
+pub mod rate_limit;
 pub mod render;
+pub mod unlock;
 
 pub use counter::HitCounter;
-
-pub const PASSWORD_COOKIE: &str = "password";
+pub use unlock::UnlockToken;
 
 #[derive(rocket::Responder)]
 enum PageError {
@@ -19,6 +26,8 @@ enum PageError {
     Render(String),
     #[response(status = 500)]
     NotFound(String),
+    #[response(status = 403)]
+    Forbidden(String),
     #[response(status = 500)]
     Internal(String),
 }
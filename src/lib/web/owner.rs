@@ -0,0 +1,32 @@
+//! Remembers, per browser, the edit tokens of clips created through the web form.
+
+use crate::domain::clip::field::EditToken;
+use crate::Shortcode;
+use rocket::http::{Cookie, CookieJar, SameSite};
+use std::str::FromStr;
+
+const OWNER_COOKIE_PREFIX: &str = "owner-";
+
+fn cookie_name(shortcode: &Shortcode) -> String {
+    format!("{}{}", OWNER_COOKIE_PREFIX, shortcode.as_str())
+}
+
+pub fn remember(shortcode: &Shortcode, edit_token: &EditToken, cookies: &CookieJar<'_>) {
+    let cookie = Cookie::build(cookie_name(shortcode), edit_token.as_str().to_owned())
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .permanent()
+        .finish();
+    cookies.add_private(cookie);
+}
+
+pub fn edit_token(shortcode: &Shortcode, cookies: &CookieJar<'_>) -> Option<EditToken> {
+    cookies
+        .get_private(&cookie_name(shortcode))
+        .and_then(|cookie| EditToken::from_str(cookie.value()).ok())
+}
+
+pub fn forget(shortcode: &Shortcode, cookies: &CookieJar<'_>) {
+    cookies.remove_private(Cookie::named(cookie_name(shortcode)));
+}
@@ -0,0 +1,194 @@
+//! Token bucket rate limiting, per API key for the JSON API and per client IP for
+//! the HTML pages.
+//!
+//! Guards that reject a request leave the wait time in the request local cache so
+//! the 429 catchers can send it back as `Retry-After`.
+
+use parking_lot::Mutex;
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder};
+use rocket::State;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Buckets are only dropped once there are this many of them, and only the full ones.
+const PRUNE_AFTER: usize = 10_000;
+
+/// Allows bursts of `burst` requests, refilled at `per_minute`.
+#[derive(Clone, Copy, Debug)]
+pub struct Rate {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl Rate {
+    fn per_second(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// One token bucket per caller, `None` rate disables limiting.
+#[derive(Debug)]
+struct Buckets {
+    rate: Option<Rate>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Buckets {
+    fn new(rate: Option<Rate>) -> Self {
+        Self {
+            rate,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for `caller`, or returns how long to wait for the next one.
+    fn take(&self, caller: &str, now: Instant) -> Result<(), Duration> {
+        let rate = match self.rate {
+            Some(rate) if rate.per_minute > 0 => rate,
+            _ => return Ok(()),
+        };
+        let burst = f64::from(rate.burst.max(1));
+        let mut buckets = self.buckets.lock();
+        if buckets.len() > PRUNE_AFTER {
+            buckets.retain(|_, bucket| {
+                let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens + elapsed * rate.per_second() < burst
+            });
+        }
+        let bucket = buckets.entry(caller.to_owned()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate.per_second()).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / rate.per_second();
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+}
+
+/// Request rates shared by every route, managed by Rocket.
+#[derive(Debug)]
+pub struct RateLimiter {
+    api: Buckets,
+    html: Buckets,
+}
+
+impl RateLimiter {
+    pub fn new(api: Option<Rate>, html: Option<Rate>) -> Self {
+        Self {
+            api: Buckets::new(api),
+            html: Buckets::new(html),
+        }
+    }
+
+    pub fn check_api_key(&self, prefix: &str) -> Result<(), Duration> {
+        self.api.take(prefix, Instant::now())
+    }
+
+    pub fn check_client(&self, ip: &str) -> Result<(), Duration> {
+        self.html.take(ip, Instant::now())
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+/// Seconds a rate limited caller should wait, read by the 429 catchers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RetryAfter(pub Option<u64>);
+
+impl RetryAfter {
+    /// Remembers the wait for the catcher, rounded up to whole seconds.
+    pub fn remember(req: &Request<'_>, wait: Duration) {
+        let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        req.local_cache(|| RetryAfter(Some(secs.max(1))));
+    }
+
+    pub fn of(req: &Request<'_>) -> Self {
+        *req.local_cache(RetryAfter::default)
+    }
+}
+
+/// Sends `R` as a 429, with a `Retry-After` header when the wait is known.
+pub struct TooManyRequests<R>(pub R, pub RetryAfter);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for TooManyRequests<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.0.respond_to(req)?;
+        response.set_status(Status::TooManyRequests);
+        if let Some(secs) = (self.1).0 {
+            response.set_header(Header::new("Retry-After", secs.to_string()));
+        }
+        Ok(response)
+    }
+}
+
+/// Limits the HTML routes per client IP. Requests without a known address pass.
+pub struct ClientRateLimit;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientRateLimit {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let limiter = match req.guard::<&State<RateLimiter>>().await {
+            Outcome::Success(limiter) => limiter,
+            _ => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+        let ip = match req.client_ip() {
+            Some(ip) => ip.to_string(),
+            None => return Outcome::Success(ClientRateLimit),
+        };
+        match limiter.check_client(&ip) {
+            Ok(()) => Outcome::Success(ClientRateLimit),
+            Err(wait) => {
+                RetryAfter::remember(req, wait);
+                Outcome::Failure((Status::TooManyRequests, ()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let buckets = Buckets::new(Some(Rate {
+            per_minute: 60,
+            burst: 2,
+        }));
+        let start = Instant::now();
+        assert!(buckets.take("key", start).is_ok());
+        assert!(buckets.take("key", start).is_ok());
+        let wait = buckets.take("key", start).unwrap_err();
+        assert_eq!(wait.as_secs(), 1);
+        assert!(buckets.take("other", start).is_ok());
+        assert!(buckets.take("key", start + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn no_rate_means_no_limit() {
+        let buckets = Buckets::new(None);
+        let now = Instant::now();
+        assert!((0..1000).all(|_| buckets.take("key", now).is_ok()));
+    }
+}
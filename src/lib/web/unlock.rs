@@ -0,0 +1,78 @@
+use crate::domain::unlock::Unlock;
+use crate::{Clip, Shortcode};
+use rocket::http::{Cookie, CookieJar, SameSite};
+use rocket::time::Duration;
+
+/// How long a successful password entry keeps a clip unlocked, in seconds.
+pub const UNLOCK_TTL: i64 = 60 * 60;
+
+const UNLOCK_COOKIE_PREFIX: &str = "unlock-";
+
+/// Keeps an [`Unlock`] in the browser that entered the password of a clip.
+///
+/// Stored as a Rocket private cookie, so the value is encrypted and authenticated
+/// with the server secret and the real password never reaches the client. Whether the
+/// unlock still opens the clip is checked by the service.
+pub struct UnlockToken;
+
+impl UnlockToken {
+    fn cookie_name(shortcode: &Shortcode) -> String {
+        format!("{}{}", UNLOCK_COOKIE_PREFIX, shortcode.as_str())
+    }
+
+    fn parse(value: &str) -> Option<Unlock> {
+        let mut parts = value.split('|');
+        let unlock = Unlock {
+            clip_id: parts.next()?.to_owned(),
+            fingerprint: parts.next()?.to_owned(),
+            expires: parts.next()?.parse().ok()?,
+        };
+        parts.next().is_none().then_some(unlock)
+    }
+
+    /// Remembers that the browser entered the current password of `clip`.
+    pub fn issue(clip: &Clip, cookies: &CookieJar<'_>) {
+        let unlock = Unlock::new(clip, chrono::Duration::seconds(UNLOCK_TTL));
+        let cookie = Cookie::build(
+            Self::cookie_name(&clip.shortcode),
+            format!(
+                "{}|{}|{}",
+                unlock.clip_id, unlock.fingerprint, unlock.expires
+            ),
+        )
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::seconds(UNLOCK_TTL))
+        .finish();
+        cookies.add_private(cookie);
+    }
+
+    /// The unlock the request carries for `shortcode`, if any.
+    pub fn find(shortcode: &Shortcode, cookies: &CookieJar<'_>) -> Option<Unlock> {
+        cookies
+            .get_private(&Self::cookie_name(shortcode))
+            .and_then(|cookie| Self::parse(cookie.value()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UnlockToken;
+    use crate::domain::unlock::Unlock;
+
+    #[test]
+    fn cookie_value_is_parsed() {
+        assert_eq!(
+            UnlockToken::parse("id|fp|42"),
+            Some(Unlock {
+                clip_id: "id".to_owned(),
+                fingerprint: "fp".to_owned(),
+                expires: 42,
+            })
+        );
+        assert!(UnlockToken::parse("aaaa|0").is_none());
+        assert!(UnlockToken::parse("id|fp|42|extra").is_none());
+        assert!(UnlockToken::parse("id|fp|soon").is_none());
+    }
+}